@@ -17,6 +17,12 @@ import {
   TextDocumentChangeEvent,
   Location,
   ProviderResult,
+  commands,
+  TextDocumentContentProvider,
+  Uri,
+  ViewColumn,
+  debug,
+  DebugAdapterExecutable,
 } from "vscode";
 
 import {
@@ -25,6 +31,7 @@ import {
   LanguageClient,
   LanguageClientOptions,
   ServerOptions,
+  ExecuteCommandRequest,
 } from "vscode-languageclient/node";
 
 let client: LanguageClient;
@@ -66,6 +73,15 @@ export async function activate(context: ExtensionContext) {
   // Create the language client and start the client.
   client = new LanguageClient("lang-lsp", "lang lsp", serverOptions, clientOptions);
   activateInlayHints(context);
+  activateDebugViews(context);
+  // The same binary serves the debug sessions with `lang-lsp dap`
+  context.subscriptions.push(
+    debug.registerDebugAdapterDescriptorFactory("lang", {
+      // Started in the workspace folder, imports are found from there when the launch has no cwd
+      createDebugAdapterDescriptor: session =>
+        new DebugAdapterExecutable(command, ["dap"], { cwd: session.workspaceFolder?.uri.fsPath }),
+    })
+  );
   client.start();
 }
 
@@ -145,3 +161,61 @@ export function activateInlayHints(ctx: ExtensionContext) {
 
   maybeUpdater.onConfigChange().catch(console.error);
 }
+
+// Read only documents with what the server computed for the active file
+const DEBUG_SCHEME = "lang-debug";
+// The explanation of the diagnostic codes, their links point here and the server has the text
+const DIAGNOSTICS_DOC = "diagnostics.md";
+const DEBUG_VIEWS: [string, string][] = [
+  ["showSyntaxTree", "Syntax Tree"],
+  ["showTypeTable", "Type Table"],
+  ["showTokens", "Tokens"],
+];
+
+export function activateDebugViews(ctx: ExtensionContext) {
+  // The commands fill the text before opening the document
+  const contents = new Map<string, string>();
+  ctx.subscriptions.push(
+    workspace.registerTextDocumentContentProvider(
+      DEBUG_SCHEME,
+      new (class implements TextDocumentContentProvider {
+        provideTextDocumentContent(uri: Uri): ProviderResult<string> {
+          if (uri.path === DIAGNOSTICS_DOC) {
+            return client.sendRequest("custom/diagnostics_doc").then(
+              text => text as string,
+              err => ""
+            );
+          }
+          return contents.get(uri.toString()) ?? "";
+        }
+      })()
+    )
+  );
+
+  async function show(title: string, text: string) {
+    // The time makes every call open a new document instead of showing a cached text
+    const uri = Uri.parse(`${DEBUG_SCHEME}:${title}`).with({ query: `${Date.now()}` });
+    contents.set(uri.toString(), text);
+    const document = await workspace.openTextDocument(uri);
+    await window.showTextDocument(document, { viewColumn: ViewColumn.Beside, preview: true });
+  }
+
+  // The server commands are registered by the client library, ours only open the result
+  for (const [name, title] of DEBUG_VIEWS) {
+    ctx.subscriptions.push(
+      commands.registerCommand(`lang-lsp.${name}`, async () => {
+        const editor = window.activeTextEditor;
+        if (!editor || editor.document.languageId !== "lang") {
+          return;
+        }
+        const text = await client
+          .sendRequest(ExecuteCommandRequest.type, {
+            command: `lang.${name}`,
+            arguments: [editor.document.uri.toString()],
+          })
+          .catch(err => null);
+        await show(title, (text as string | null) ?? "");
+      })
+    );
+  }
+}
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+// Time we wait after an edit before analysing, so fast typing only analyses the last version
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Maximum time a request waits for the analysis of the latest version before giving up
+pub const ANALYSIS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Clients never send this version, so no analysis is the latest once a document is closed
+const CLOSED: i32 = i32::MIN;
+
+// Why a version of a document has to be analysed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    Open,
+    // Waits for the typing to stop before analysing
+    Edit,
+    // Something else changed, like the config or an imported file. The files that import this
+    // one are not checked again, they do not see any difference
+    Recheck,
+}
+
+// A version waiting for the task of its document. Only the last one is kept, the task never
+// analyses a version that was already replaced
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub text: String,
+    pub version: i32,
+    pub reason: Reason,
+}
+
+// Tracks which version of a document has been received and which one has been analysed
+#[derive(Debug)]
+pub struct AnalysisState {
+    latest: watch::Sender<i32>,
+    analyzed: watch::Sender<i32>,
+    jobs: watch::Sender<Option<Job>>,
+    // Scheduling looks at the latest version before replacing it, two at once could lose one
+    scheduling: Mutex<()>,
+    // Held while a snapshot is stored and its diagnostics sent, and while the document closes
+    publishing: tokio::sync::Mutex<()>,
+    // Keeping a receiver also keeps the channel open, so send never fails
+    latest_rx: watch::Receiver<i32>,
+    analyzed_rx: watch::Receiver<i32>,
+    jobs_rx: watch::Receiver<Option<Job>>,
+}
+
+impl Default for AnalysisState {
+    fn default() -> Self {
+        let (latest, latest_rx) = watch::channel(CLOSED);
+        let (analyzed, analyzed_rx) = watch::channel(CLOSED);
+        let (jobs, jobs_rx) = watch::channel(None);
+        AnalysisState {
+            latest,
+            analyzed,
+            jobs,
+            scheduling: Mutex::new(()),
+            publishing: tokio::sync::Mutex::new(()),
+            latest_rx,
+            analyzed_rx,
+            jobs_rx,
+        }
+    }
+}
+
+impl AnalysisState {
+    // A new version arrived, any analysis of an older one is now useless
+    pub fn schedule(&self, job: Job) {
+        let _scheduling = self.scheduling.lock().unwrap();
+        // A newer version is already waiting, it will see whatever made us check this one again
+        if job.reason == Reason::Recheck && *self.latest_rx.borrow() != job.version {
+            return;
+        }
+        // The same version again is not analysed yet either, requests have to wait for it
+        if *self.analyzed_rx.borrow() == job.version {
+            let _ = self.analyzed.send(CLOSED);
+        }
+        let _ = self.latest.send(job.version);
+        let _ = self.jobs.send(Some(job));
+    }
+
+    // The jobs of the document, for the task that analyses it
+    pub fn jobs(&self) -> watch::Receiver<Option<Job>> {
+        self.jobs_rx.clone()
+    }
+
+    // The document was closed, any analysis still running for it is discarded and the task stops
+    pub fn close(&self) {
+        let _ = self.latest.send(CLOSED);
+        let _ = self.analyzed.send(CLOSED);
+        let _ = self.jobs.send(None);
+    }
+
+    // Whoever holds it can check the version knowing the document will not close meanwhile
+    pub async fn publishing(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.publishing.lock().await
+    }
+
+    pub fn is_latest(&self, version: i32) -> bool {
+        *self.latest_rx.borrow() == version
+    }
+
+    pub fn finish(&self, version: i32) {
+        let _ = self.analyzed.send(version);
+    }
+
+    fn is_up_to_date(&self) -> bool {
+        *self.analyzed_rx.borrow() == *self.latest_rx.borrow()
+    }
+
+    // Waits until the latest version received has been analysed. Returns false on timeout
+    pub async fn wait(&self) -> bool {
+        let mut analyzed = self.analyzed_rx.clone();
+        let wait = async {
+            while !self.is_up_to_date() {
+                if analyzed.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(ANALYSIS_TIMEOUT, wait).await.is_ok() && self.is_up_to_date()
+    }
+}
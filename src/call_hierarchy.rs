@@ -0,0 +1,91 @@
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    token::Span,
+};
+
+use crate::scope::{BindingKind, Scopes};
+
+// A call edge, caller is None when the call happens at the top level of the module
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub caller: Option<usize>,
+    pub callee: usize,
+    pub span: Span,
+}
+
+pub fn is_function(scopes: &Scopes, binding: usize) -> bool {
+    let binding = &scopes.bindings[binding];
+    binding.kind == BindingKind::Variable && binding.is_lambda
+}
+
+fn collect_calls(
+    node: &Anotated<Ast>,
+    scopes: &Scopes,
+    caller: Option<usize>,
+    calls: &mut Vec<Call>,
+) {
+    match &node.0 {
+        Ast::Declaration(pattern, _, _, _, Some(value)) => {
+            // The body of a lambda bound to a name belongs to that function
+            let caller = if matches!(value.0, Ast::Lambda(..)) {
+                scopes
+                    .binding_at(pattern.1.start)
+                    .filter(|binding| is_function(scopes, *binding))
+                    .or(caller)
+            } else {
+                caller
+            };
+            collect_calls(value, scopes, caller, calls);
+        }
+        Ast::Call(callee, args) => {
+            if let Ast::Variable((_, span)) = &callee.0 {
+                if let Some(binding) = scopes.resolve_use(span) {
+                    if is_function(scopes, binding) {
+                        calls.push(Call {
+                            caller,
+                            callee: binding,
+                            span: span.clone(),
+                        });
+                    }
+                }
+            }
+            collect_calls(callee, scopes, caller, calls);
+            for arg in args {
+                collect_calls(arg, scopes, caller, calls);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            collect_calls(l, scopes, caller, calls);
+            collect_calls(r, scopes, caller, calls);
+        }
+        Ast::While(_, cond, body) => {
+            collect_calls(cond, scopes, caller, calls);
+            collect_calls(body, scopes, caller, calls);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            collect_calls(cond, scopes, caller, calls);
+            collect_calls(if_body, scopes, caller, calls);
+            collect_calls(else_body, scopes, caller, calls);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                collect_calls(arg, scopes, caller, calls);
+            }
+        }
+        Ast::Lambda(args, _, body) => {
+            for arg in args {
+                collect_calls(arg, scopes, caller, calls);
+            }
+            collect_calls(body, scopes, caller, calls);
+        }
+        _ => (),
+    }
+}
+
+pub fn get_calls(ast: &[Anotated<Ast>], scopes: &Scopes) -> Vec<Call> {
+    let mut calls = Vec::new();
+    for node in ast {
+        collect_calls(node, scopes, None, &mut calls);
+    }
+    calls
+}
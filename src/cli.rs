@@ -0,0 +1,743 @@
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+use serde_json::json;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Url};
+
+use crate::config::Config;
+use crate::dap;
+use crate::database::{analyze_file, Database};
+use crate::diagnostics::{code_explanation, find_lang_files};
+use crate::dump::{dump_ast, dump_semantic_tokens, dump_tokens, dump_types};
+use crate::formatter::format;
+use crate::imports::link;
+
+const USAGE: &str = "Usage:
+    lang-lsp                                   Start the language server on stdio
+    lang-lsp check [--format human|json|sarif] <paths...>
+    lang-lsp fmt [--check] <paths...>
+    lang-lsp dump tokens|ast|types|semantic-tokens <file>
+    lang-lsp dap                               Start a debug adapter on stdio";
+
+// Exit codes of the subcommands
+const EXIT_OK: i32 = 0;
+const EXIT_ERRORS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+    Sarif,
+}
+
+// A file of the command line with what the server would report for it
+struct CheckedFile {
+    path: PathBuf,
+    uri: Url,
+    rope: Rope,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Runs the subcommand in args and returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("check") => check(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("dump") => dump(&args[1..]),
+        Some("dap") if args.len() == 1 => {
+            dap::run();
+            EXIT_OK
+        }
+        Some("dap") => usage_error("dap takes no arguments"),
+        Some("help" | "--help" | "-h") => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        _ => usage_error("unknown command"),
+    }
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    EXIT_USAGE
+}
+
+// The files named in the command line, the directories are searched for .lang files
+fn collect_paths(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            find_lang_files(&path, &mut files);
+        } else if path.is_file() {
+            files.push(path);
+        } else {
+            return Err(format!("{} does not exist", path.display()));
+        }
+    }
+    Ok(files)
+}
+
+// The current directory plays the part of the workspace folder
+fn workspace_roots() -> Vec<PathBuf> {
+    std::env::current_dir().into_iter().collect()
+}
+
+fn check_file(
+    database: &Database,
+    path: PathBuf,
+    roots: &[PathBuf],
+    config: &Config,
+) -> Result<CheckedFile, String> {
+    let path = path.canonicalize().unwrap_or(path);
+    let uri = Url::from_file_path(&path)
+        .map_err(|_| format!("{} is not a valid path", path.display()))?;
+    let text =
+        std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let read = |uri: &Url| std::fs::read_to_string(uri.to_file_path().ok()?).ok();
+    let linked = link(&uri, &text, roots, &read);
+    let diagnostics = analyze_file(database, &uri, &linked, config).diagnostics;
+    Ok(CheckedFile {
+        path,
+        uri,
+        rope: Rope::from_str(&text),
+        diagnostics,
+    })
+}
+
+// Prints what the server computes for a file, to see why highlighting or hover are wrong
+fn dump(args: &[String]) -> i32 {
+    let (what, path) = match args {
+        [what, path] => (what.as_str(), PathBuf::from(path)),
+        _ => return usage_error("dump expects what to dump and a file"),
+    };
+    if !matches!(what, "tokens" | "ast" | "types" | "semantic-tokens") {
+        return usage_error(&format!("can not dump {}", what));
+    }
+
+    let roots = workspace_roots();
+    let config = Config::load(&roots).unwrap_or_default();
+    let path = path.canonicalize().unwrap_or(path);
+    let uri = match Url::from_file_path(&path) {
+        Ok(uri) => uri,
+        Err(_) => return usage_error(&format!("{} is not a valid path", path.display())),
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("error: {}: {}", path.display(), err);
+            return EXIT_USAGE;
+        }
+    };
+
+    let read = |uri: &Url| std::fs::read_to_string(uri.to_file_path().ok()?).ok();
+    let linked = link(&uri, &text, &roots, &read);
+    let analysis = analyze_file(&Database::default(), &uri, &linked, &config);
+    let rope = Rope::from_str(&text);
+    let output = match what {
+        "tokens" => dump_tokens(&analysis.tokens, &rope),
+        "ast" => dump_ast(&analysis.ast, &analysis.type_table, &rope),
+        "types" => dump_types(&analysis.ast, &analysis.type_table, &rope),
+        _ => dump_semantic_tokens(&analysis.ast, &analysis.type_table, &rope),
+    };
+    print!("{}", output);
+    EXIT_OK
+}
+
+fn check(args: &[String]) -> i32 {
+    let mut format = Format::Human;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().map(String::as_str) {
+                    Some("human") => Format::Human,
+                    Some("json") => Format::Json,
+                    Some("sarif") => Format::Sarif,
+                    _ => return usage_error("--format expects human, json or sarif"),
+                }
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        return usage_error("check expects at least one path");
+    }
+
+    let roots = workspace_roots();
+    let config = match Config::load(&roots) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("error: invalid {}", err);
+            return EXIT_USAGE;
+        }
+    };
+    let files = match collect_paths(&paths) {
+        Ok(files) => files,
+        Err(err) => return usage_error(&err),
+    };
+
+    // The files imported by several of them are inferred once
+    let database = Database::default();
+    let mut checked = Vec::new();
+    for path in files {
+        match check_file(&database, path, &roots, &config) {
+            Ok(file) => checked.push(file),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                return EXIT_USAGE;
+            }
+        }
+    }
+
+    match format {
+        Format::Human => print_human(&checked),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_json(&checked)).unwrap()
+        ),
+        Format::Sarif => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_sarif(&checked)).unwrap()
+        ),
+    }
+
+    let has_errors = checked
+        .iter()
+        .flat_map(|file| &file.diagnostics)
+        .any(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR));
+    if has_errors {
+        EXIT_ERRORS
+    } else {
+        EXIT_OK
+    }
+}
+
+fn severity_name(severity: Option<DiagnosticSeverity>) -> &'static str {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => "error",
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        _ => "hint",
+    }
+}
+
+fn code_name(diagnostic: &Diagnostic) -> Option<String> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code.clone()),
+        Some(NumberOrString::Number(code)) => Some(code.to_string()),
+        None => None,
+    }
+}
+
+// The path of a file relative to the current directory when possible
+fn display_path(path: &Path) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+// error[E0002]: Unexpected token in input, expected )
+//  --> src/main.lang:3:9
+//   |
+// 3 | x = (1 + 2
+//   |         ^
+fn print_human(files: &[CheckedFile]) {
+    let mut errors = 0;
+    let mut warnings = 0;
+    for file in files {
+        for diagnostic in &file.diagnostics {
+            match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => errors += 1,
+                Some(DiagnosticSeverity::WARNING) => warnings += 1,
+                _ => (),
+            }
+
+            let start = diagnostic.range.start;
+            let end = diagnostic.range.end;
+            match code_name(diagnostic) {
+                Some(code) => println!(
+                    "{}[{}]: {}",
+                    severity_name(diagnostic.severity),
+                    code,
+                    diagnostic.message
+                ),
+                None => println!(
+                    "{}: {}",
+                    severity_name(diagnostic.severity),
+                    diagnostic.message
+                ),
+            }
+            println!(
+                " --> {}:{}:{}",
+                display_path(&file.path),
+                start.line + 1,
+                start.character + 1
+            );
+
+            let line_number = (start.line + 1).to_string();
+            let gutter = " ".repeat(line_number.len());
+            let line = file
+                .rope
+                .get_line(start.line as usize)
+                .map(|line| line.to_string())
+                .unwrap_or_default();
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            let width = if end.line == start.line {
+                end.character.saturating_sub(start.character).max(1)
+            } else {
+                (line.chars().count() as u32)
+                    .saturating_sub(start.character)
+                    .max(1)
+            };
+            println!("{} |", gutter);
+            println!("{} | {}", line_number, line);
+            println!(
+                "{} | {}{}",
+                gutter,
+                " ".repeat(start.character as usize),
+                "^".repeat(width as usize)
+            );
+
+            for related in diagnostic.related_information.iter().flatten() {
+                let path = related
+                    .location
+                    .uri
+                    .to_file_path()
+                    .map(|path| display_path(&path))
+                    .unwrap_or_else(|_| related.location.uri.to_string());
+                println!(
+                    "{} = note: {} ({}:{}:{})",
+                    gutter,
+                    related.message,
+                    path,
+                    related.location.range.start.line + 1,
+                    related.location.range.start.character + 1
+                );
+            }
+            println!();
+        }
+    }
+    println!(
+        "{} files checked: {} errors, {} warnings",
+        files.len(),
+        errors,
+        warnings
+    );
+}
+
+fn to_json(files: &[CheckedFile]) -> serde_json::Value {
+    json!(files
+        .iter()
+        .map(|file| json!({
+            "path": display_path(&file.path),
+            "uri": file.uri,
+            "diagnostics": file.diagnostics,
+        }))
+        .collect::<Vec<_>>())
+}
+
+// SARIF 2.1.0, the format code scanning tools in CI understand. Lines and columns start at 1
+fn to_sarif(files: &[CheckedFile]) -> serde_json::Value {
+    let mut rules = Vec::new();
+    let mut results = Vec::new();
+    for file in files {
+        for diagnostic in &file.diagnostics {
+            let code = code_name(diagnostic);
+            if let Some(code) = &code {
+                if !rules.contains(code) {
+                    rules.push(code.clone());
+                }
+            }
+            let level = match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => "error",
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                _ => "note",
+            };
+            let range = diagnostic.range;
+            results.push(json!({
+                "ruleId": code,
+                "level": level,
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": file.uri },
+                        "region": {
+                            "startLine": range.start.line + 1,
+                            "startColumn": range.start.character + 1,
+                            "endLine": range.end.line + 1,
+                            "endColumn": range.end.character + 1,
+                        },
+                    },
+                }],
+            }));
+        }
+    }
+
+    let rules = rules
+        .iter()
+        .map(|code| {
+            let help = code_explanation(code).map(|text| json!({ "text": text }));
+            json!({ "id": code, "help": help })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+// Lines of context around each change in a diff
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+// Furthest x reached on each diagonal k = x - y, for k in -max..=max
+struct Diagonals {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Diagonals {
+    fn new(max: usize) -> Self {
+        Diagonals {
+            offset: max as isize,
+            x: vec![0; 2 * max + 1],
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.x[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.x[(k + self.offset) as usize] = x;
+    }
+}
+
+fn common_prefix(a: &[&str], b: &[&str]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+fn common_suffix(a: &[&str], b: &[&str]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+// Where a shortest edit script of a into b can be split in two, searching from both ends at
+// once (Myers' middle snake). Only needs memory for the diagonals
+fn middle_snake(
+    a: &[&str],
+    b: &[&str],
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    forward.set(1, 0);
+    backward.set(1, 0);
+    for d in 0..=(n + m + 1) / 2 {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < a.len() && y < b.len() {
+                x += common_prefix(&a[x..], &b[y..]);
+            }
+            forward.set(k, x);
+            if odd && (k - delta).abs() < d && x + backward.get(delta - k) >= a.len() {
+                return start;
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            } else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < a.len() && y < b.len() {
+                let len = common_suffix(&a[..a.len() - x], &b[..b.len() - y]);
+                x += len;
+                y += len;
+            }
+            backward.set(k, x);
+            if !odd && (k - delta).abs() <= d && x + forward.get(delta - k) >= a.len() {
+                return (a.len() - x, b.len() - y);
+            }
+        }
+    }
+    unreachable!("the paths from both ends always meet")
+}
+
+fn diff_range<'a>(
+    a: &[&'a str],
+    b: &[&'a str],
+    forward: &mut Diagonals,
+    backward: &mut Diagonals,
+    edits: &mut Vec<Edit<'a>>,
+) {
+    let prefix = common_prefix(a, b);
+    edits.extend(a[..prefix].iter().map(|line| Edit::Equal(line)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = common_suffix(a, b);
+    let (changed_a, changed_b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if changed_a.is_empty() {
+        edits.extend(changed_b.iter().map(|line| Edit::Insert(line)));
+    } else if changed_b.is_empty() {
+        edits.extend(changed_a.iter().map(|line| Edit::Delete(line)));
+    } else {
+        let (x, y) = middle_snake(changed_a, changed_b, forward, backward);
+        diff_range(&changed_a[..x], &changed_b[..y], forward, backward, edits);
+        diff_range(&changed_a[x..], &changed_b[y..], forward, backward, edits);
+    }
+    edits.extend(a[a.len() - suffix..].iter().map(|line| Edit::Equal(line)));
+}
+
+// Shortest line diff, in linear memory so checking a big file never allocates a table of
+// old lines times new lines
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let max = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = Diagonals::new(max);
+    let mut backward = Diagonals::new(max);
+    let mut edits = Vec::new();
+    diff_range(old, new, &mut forward, &mut backward, &mut edits);
+    // In each change the deleted lines go first, like diff prints them
+    let mut start = 0;
+    while start < edits.len() {
+        let len = edits[start..]
+            .iter()
+            .take_while(|edit| !matches!(edit, Edit::Equal(_)))
+            .count();
+        edits[start..start + len].sort_by_key(|edit| matches!(edit, Edit::Insert(_)));
+        start += len.max(1);
+    }
+    edits
+}
+
+fn push_diff_line(diff: &mut String, prefix: char, line: &str) {
+    diff.push(prefix);
+    diff.push_str(line);
+    if !line.ends_with('\n') {
+        diff.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let edits = diff_lines(&old_lines, &new_lines);
+    let changes = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    let mut diff = format!("--- {}\n+++ {}\n", path, path);
+    let mut next = 0;
+    while next < changes.len() {
+        // Changes closer than twice the context go in the same hunk
+        let first = changes[next];
+        let mut last = first;
+        while next + 1 < changes.len() && changes[next + 1] <= last + 2 * DIFF_CONTEXT {
+            next += 1;
+            last = changes[next];
+        }
+        next += 1;
+
+        let start = first.saturating_sub(DIFF_CONTEXT);
+        let end = (last + DIFF_CONTEXT + 1).min(edits.len());
+        let hunk = &edits[start..end];
+        let is_old = |edit: &&Edit| !matches!(edit, Edit::Insert(_));
+        let is_new = |edit: &&Edit| !matches!(edit, Edit::Delete(_));
+        // An empty side starts at the line before the hunk, not at the first line of it
+        let range = |before: usize, len: usize| {
+            if len == 0 {
+                format!("{},0", before)
+            } else {
+                format!("{},{}", before + 1, len)
+            }
+        };
+        diff.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(
+                edits[..start].iter().filter(is_old).count(),
+                hunk.iter().filter(is_old).count()
+            ),
+            range(
+                edits[..start].iter().filter(is_new).count(),
+                hunk.iter().filter(is_new).count()
+            ),
+        ));
+        for edit in hunk {
+            match edit {
+                Edit::Equal(line) => push_diff_line(&mut diff, ' ', line),
+                Edit::Delete(line) => push_diff_line(&mut diff, '-', line),
+                Edit::Insert(line) => push_diff_line(&mut diff, '+', line),
+            }
+        }
+    }
+    diff
+}
+
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths = args
+        .iter()
+        .filter(|arg| *arg != "--check")
+        .cloned()
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return usage_error("fmt expects at least one path");
+    }
+    let files = match collect_paths(&paths) {
+        Ok(files) => files,
+        Err(err) => return usage_error(&err),
+    };
+
+    let mut failed = false;
+    for path in files {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("error: {}: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+        // The same formatter the editor uses, so both always agree
+        let formatted = match format(&text) {
+            Some(formatted) => formatted,
+            None => {
+                eprintln!("error: {} can not be formatted", display_path(&path));
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        if check {
+            print!("{}", unified_diff(&display_path(&path), &text, &formatted));
+            failed = true;
+        } else if let Err(err) = std::fs::write(&path, formatted) {
+            eprintln!("error: {}: {}", path.display(), err);
+            failed = true;
+        }
+    }
+
+    if failed {
+        EXIT_ERRORS
+    } else {
+        EXIT_OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Length of the longest common subsequence, with the whole table
+    fn lcs(a: &[&str], b: &[&str]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = if a[i] == b[j] {
+                    table[i][j] + 1
+                } else {
+                    table[i][j + 1].max(table[i + 1][j])
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    // Lines from a few letters, so they repeat and the diffs are not trivial
+    fn lines(seed: &mut u64, len: usize) -> Vec<&'static str> {
+        const LINES: &[&str] = &["a\n", "b\n", "c\n", "d\n"];
+        (0..len)
+            .map(|_| {
+                *seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                LINES[(*seed >> 33) as usize % LINES.len()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_is_a_shortest_edit_script() {
+        let mut seed = 1;
+        for _ in 0..500 {
+            let (old_len, new_len) = ((seed >> 40) as usize % 12, (seed >> 50) as usize % 12);
+            let old = lines(&mut seed, old_len);
+            let new = lines(&mut seed, new_len);
+            let edits = diff_lines(&old, &new);
+
+            let before = edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    Edit::Equal(line) | Edit::Delete(line) => Some(*line),
+                    Edit::Insert(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let after = edits
+                .iter()
+                .filter_map(|edit| match edit {
+                    Edit::Equal(line) | Edit::Insert(line) => Some(*line),
+                    Edit::Delete(_) => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(before, old);
+            assert_eq!(after, new);
+            let equal = edits
+                .iter()
+                .filter(|edit| matches!(edit, Edit::Equal(_)))
+                .count();
+            assert_eq!(equal, lcs(&old, &new), "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn unified_diff_has_hunks_with_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+        assert_eq!(
+            unified_diff("x.lang", old, new),
+            "--- x.lang\n+++ x.lang\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    token::Span,
+};
+use ropey::Rope;
+use serde::Deserialize;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
+
+use crate::lints::Lint;
+
+// Project configuration, read from the root of the workspace
+pub const CONFIG_FILE: &str = "lang.toml";
+
+// Comment that silences the diagnostics with the given codes in its line and the next one
+const IGNORE_DIRECTIVE: &str = "lang-ignore:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Off,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LintLevel {
+    pub fn severity(&self) -> Option<DiagnosticSeverity> {
+        match self {
+            LintLevel::Off => None,
+            LintLevel::Hint => Some(DiagnosticSeverity::HINT),
+            LintLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            LintLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            LintLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+// Each category of inlay hints can be switched off on its own
+#[derive(Debug, Clone, Deserialize)]
+pub struct InlayHintsConfig {
+    // The inferred type of `:=` declarations
+    #[serde(default = "enabled")]
+    pub types: bool,
+    // The value of declarations initialized with a constant expression
+    #[serde(default = "enabled")]
+    pub values: bool,
+}
+
+impl Default for InlayHintsConfig {
+    fn default() -> Self {
+        InlayHintsConfig {
+            types: true,
+            values: true,
+        }
+    }
+}
+
+// [lints]
+// unused-variable = "off"
+// shadowing = "error"
+//
+// [inlay-hints]
+// values = false
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub lints: HashMap<String, LintLevel>,
+    #[serde(default, rename = "inlay-hints")]
+    pub inlay_hints: InlayHintsConfig,
+}
+
+impl Config {
+    pub fn lint_level(&self, lint: Lint) -> LintLevel {
+        self.lints
+            .get(lint.code())
+            .copied()
+            .unwrap_or_else(|| lint.default_level())
+    }
+
+    // The first lang.toml found in the workspace folders, or the default config if there is none
+    pub fn load(folders: &[PathBuf]) -> Result<Config, String> {
+        for folder in folders {
+            let path = folder.join(CONFIG_FILE);
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                return toml::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err));
+            }
+        }
+        Ok(Config::default())
+    }
+}
+
+fn collect_comments(node: &Anotated<Ast>, comments: &mut Vec<Span>) {
+    match &node.0 {
+        Ast::Coment((_, span)) => comments.push(span.clone()),
+        Ast::Declaration(_, _, _, _, Some(value)) => collect_comments(value, comments),
+        Ast::Call(caller, args) => {
+            collect_comments(caller, comments);
+            for arg in args {
+                collect_comments(arg, comments);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            collect_comments(l, comments);
+            collect_comments(r, comments);
+        }
+        Ast::While(_, cond, body) => {
+            collect_comments(cond, comments);
+            collect_comments(body, comments);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            collect_comments(cond, comments);
+            collect_comments(if_body, comments);
+            collect_comments(else_body, comments);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                collect_comments(arg, comments);
+            }
+        }
+        Ast::Lambda(args, _, body) => {
+            for arg in args {
+                collect_comments(arg, comments);
+            }
+            collect_comments(body, comments);
+        }
+        _ => (),
+    }
+}
+
+fn ignored_codes(text: &str, line: usize, ignored: &mut Vec<(usize, String)>) {
+    let codes = match text.find(IGNORE_DIRECTIVE) {
+        Some(i) => &text[i + IGNORE_DIRECTIVE.len()..],
+        None => return,
+    };
+    for code in codes.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        ignored.push((line, code.to_string()));
+        ignored.push((line + 1, code.to_string()));
+    }
+}
+
+// Start of the `//` comment of a line, skipping the ones inside strings
+fn line_comment(line: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut previous = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '/' if !in_string && previous == Some('/') => return Some(i - 1),
+            _ => (),
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+// (line, code) pairs silenced by a `// lang-ignore: <code>, <code>` comment. Without an AST,
+// when the parser gave up, the comments are found in the text so parse errors can be silenced too
+pub fn find_ignored(ast: Option<&[Anotated<Ast>]>, rope: &Rope) -> Vec<(usize, String)> {
+    let mut ignored = Vec::new();
+    let ast = match ast {
+        Some(ast) => ast,
+        None => {
+            for (line, text) in rope.lines().enumerate() {
+                let text = text.to_string();
+                if let Some(start) = line_comment(&text) {
+                    ignored_codes(&text[start..], line, &mut ignored);
+                }
+            }
+            return ignored;
+        }
+    };
+
+    let mut comments = Vec::new();
+    for node in ast {
+        collect_comments(node, &mut comments);
+    }
+    for span in comments {
+        let text = match rope.get_slice(span.start..span.end) {
+            Some(text) => text.to_string(),
+            None => continue,
+        };
+        ignored_codes(&text, rope.char_to_line(span.start), &mut ignored);
+    }
+    ignored
+}
+
+pub fn is_ignored(diagnostic: &Diagnostic, ignored: &[(usize, String)]) -> bool {
+    let code = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code.clone(),
+        Some(NumberOrString::Number(code)) => code.to_string(),
+        None => return false,
+    };
+    let line = diagnostic.range.start.line as usize;
+    ignored.iter().any(|(l, c)| *l == line && *c == code)
+}
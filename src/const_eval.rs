@@ -0,0 +1,173 @@
+use std::fmt;
+
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    token::Token,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Bool(b) => write!(f, "{}", b),
+            Constant::Number(n) => write!(f, "{}", n),
+            Constant::Text(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+// Shared with the interpreter, so folding and running agree
+pub fn fold_binary(l: Constant, op: &str, r: Constant) -> Option<Constant> {
+    use Constant::*;
+    Some(match (l, op, r) {
+        (Number(l), "+", Number(r)) => Number(l + r),
+        (Number(l), "-", Number(r)) => Number(l - r),
+        (Number(l), "*", Number(r)) => Number(l * r),
+        (Number(l), "/", Number(r)) if r != 0.0 => Number(l / r),
+        (Number(l), "%", Number(r)) if r != 0.0 => Number(l % r),
+        (Number(l), "<", Number(r)) => Bool(l < r),
+        (Number(l), ">", Number(r)) => Bool(l > r),
+        (Number(l), "<=", Number(r)) => Bool(l <= r),
+        (Number(l), ">=", Number(r)) => Bool(l >= r),
+        (Text(l), "+", Text(r)) => Text(l + &r),
+        (Bool(l), "and", Bool(r)) => Bool(l && r),
+        (Bool(l), "or", Bool(r)) => Bool(l || r),
+        (l, "==", r) => Bool(l == r),
+        (l, "!=", r) => Bool(l != r),
+        _ => return None,
+    })
+}
+
+// The value of an expression made only of literals, operators and ifs, None if it is not constant
+pub fn fold(node: &Anotated<Ast>) -> Option<Constant> {
+    match &node.0 {
+        Ast::Literal((Token::Bool(b), _)) => Some(Constant::Bool(*b)),
+        Ast::Literal((Token::Number(n), _)) => n.parse().ok().map(Constant::Number),
+        Ast::Literal((Token::Text(s), _)) => Some(Constant::Text(s.clone())),
+        Ast::Binary(l, (Token::Op(op), _), r) => fold_binary(fold(l)?, op, fold(r)?),
+        Ast::If(_, cond, if_body, _, else_body) => match fold(cond)? {
+            Constant::Bool(true) => fold(if_body),
+            Constant::Bool(false) => fold(else_body),
+            _ => None,
+        },
+        // A block with a single expression is just that expression
+        Ast::Block(expresions) if expresions.len() == 1 => fold(&expresions[0]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+    use Constant::*;
+
+    // The value of `x := <expression>`
+    fn fold_text(expression: &str) -> Option<Constant> {
+        let (_, ast, _) = parse_file(&format!("x := {}\n", expression));
+        let ast = ast.expect("the test program parses").0;
+        match &ast[0].0 {
+            Ast::Declaration(_, _, _, _, Some(value)) => fold(value),
+            _ => panic!("the test program is a declaration"),
+        }
+    }
+
+    #[test]
+    fn numbers_fold_and_division_by_zero_does_not() {
+        assert_eq!(
+            fold_binary(Number(7.0), "+", Number(2.0)),
+            Some(Number(9.0))
+        );
+        assert_eq!(
+            fold_binary(Number(7.0), "-", Number(2.0)),
+            Some(Number(5.0))
+        );
+        assert_eq!(
+            fold_binary(Number(7.0), "*", Number(2.0)),
+            Some(Number(14.0))
+        );
+        assert_eq!(
+            fold_binary(Number(7.0), "/", Number(2.0)),
+            Some(Number(3.5))
+        );
+        assert_eq!(
+            fold_binary(Number(7.0), "%", Number(2.0)),
+            Some(Number(1.0))
+        );
+        assert_eq!(fold_binary(Number(7.0), "/", Number(0.0)), None);
+        assert_eq!(fold_binary(Number(7.0), "%", Number(0.0)), None);
+    }
+
+    #[test]
+    fn comparisons_give_bools() {
+        assert_eq!(fold_binary(Number(1.0), "<", Number(2.0)), Some(Bool(true)));
+        assert_eq!(
+            fold_binary(Number(1.0), ">", Number(2.0)),
+            Some(Bool(false))
+        );
+        assert_eq!(
+            fold_binary(Number(2.0), "<=", Number(2.0)),
+            Some(Bool(true))
+        );
+        assert_eq!(
+            fold_binary(Number(1.0), ">=", Number(2.0)),
+            Some(Bool(false))
+        );
+        assert_eq!(
+            fold_binary(Bool(true), "and", Bool(false)),
+            Some(Bool(false))
+        );
+        assert_eq!(fold_binary(Bool(true), "or", Bool(false)), Some(Bool(true)));
+    }
+
+    #[test]
+    fn equality_works_across_types_and_the_rest_does_not() {
+        let text = |s: &str| Text(s.to_string());
+        assert_eq!(fold_binary(text("a"), "+", text("b")), Some(text("ab")));
+        assert_eq!(fold_binary(text("a"), "==", text("a")), Some(Bool(true)));
+        assert_eq!(fold_binary(Number(1.0), "==", text("1")), Some(Bool(false)));
+        assert_eq!(fold_binary(Number(1.0), "!=", Bool(true)), Some(Bool(true)));
+        assert_eq!(fold_binary(Number(1.0), "+", text("1")), None);
+        assert_eq!(fold_binary(Bool(true), "<", Bool(false)), None);
+        assert_eq!(fold_binary(text("a"), "-", text("b")), None);
+    }
+
+    #[test]
+    fn expressions_of_literals_fold() {
+        assert_eq!(fold_text("1 + 2 * 3"), Some(Number(7.0)));
+        assert_eq!(fold_text("(1 + 2) * 3"), Some(Number(9.0)));
+        assert_eq!(fold_text("2.5 * 2 == 5"), Some(Bool(true)));
+        assert_eq!(fold_text("\"a\" + \"b\""), Some(Text("ab".to_string())));
+        assert_eq!(fold_text("{ 4 }"), Some(Number(4.0)));
+    }
+
+    #[test]
+    fn ifs_fold_the_branch_the_condition_picks() {
+        assert_eq!(
+            fold_text("if 1 < 2 { \"a\" } else { \"b\" }"),
+            Some(Text("a".to_string()))
+        );
+        assert_eq!(
+            fold_text("if 1 > 2 { \"a\" } else { \"b\" }"),
+            Some(Text("b".to_string()))
+        );
+        // The other branch does not have to be constant
+        assert_eq!(fold_text("if true { 1 } else { y }"), Some(Number(1.0)));
+        assert_eq!(fold_text("if 1 { 1 } else { 2 }"), None);
+    }
+
+    #[test]
+    fn names_calls_and_bigger_blocks_are_not_constant() {
+        assert_eq!(fold_text("y + 1"), None);
+        assert_eq!(fold_text("f(1)"), None);
+        assert_eq!(fold_text("{\n    1\n    2\n}"), None);
+        assert_eq!(fold_text("1 / 0"), None);
+    }
+}
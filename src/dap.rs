@@ -0,0 +1,734 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chumsky::error::SimpleReason;
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    inferer::Inferer,
+    parse_file,
+    token::{Span, Token},
+    types::Type,
+};
+use ropey::Rope;
+use serde_json::{json, Value as Json};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+use typed_arena::Arena;
+
+use crate::config::Config;
+use crate::database::{analyze_file, Analysis, Database};
+use crate::holes::mask_holes;
+use crate::imports::link;
+use crate::interpreter::{child, lookup, Debugger, Env, Frame, Interpreter, RuntimeError, Value};
+
+// The interpreter runs in a single thread
+const THREAD_ID: i64 = 1;
+
+// A file of the program, the imports first and the launched file last, in the order the
+// interpreter loads them
+struct Module {
+    path: PathBuf,
+    rope: Rope,
+    ast: Vec<Anotated<Ast>>,
+}
+
+struct Program {
+    modules: Vec<Module>,
+    type_table: Vec<Type>,
+}
+
+// What the reader thread and the interpreter thread share
+struct Shared {
+    output: Mutex<io::Stdout>,
+    seq: AtomicI64,
+    // Canonical path -> 0 based lines, already moved to the statement they stop at
+    breakpoints: Mutex<HashMap<PathBuf, Vec<usize>>>,
+    // Set by disconnect, the interpreter ends at the next statement
+    stopped: AtomicBool,
+    // Only a paused interpreter reads its requests, the others are answered by the reader
+    paused: AtomicBool,
+}
+
+impl Shared {
+    fn send(&self, mut message: Json) {
+        let mut output = self.output.lock().unwrap();
+        message["seq"] = json!(self.seq.fetch_add(1, Ordering::Relaxed) + 1);
+        let text = message.to_string();
+        let _ = write!(output, "Content-Length: {}\r\n\r\n{}", text.len(), text);
+        let _ = output.flush();
+    }
+
+    fn respond(&self, request: &Json, result: Result<Json, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn output(&self, category: &str, text: String) {
+        self.event("output", json!({ "category": category, "output": text }));
+    }
+
+    fn is_breakpoint(&self, path: &Path, line: usize) -> bool {
+        self.breakpoints
+            .lock()
+            .unwrap()
+            .get(path)
+            .is_some_and(|lines| lines.contains(&line))
+    }
+}
+
+// Content-Length framed JSON, like the language server. None once the client is gone
+fn read_message(reader: &mut impl BufRead) -> Option<Json> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn analyze_path(path: &str, roots: &[PathBuf]) -> Result<(PathBuf, String, Analysis), String> {
+    let path = PathBuf::from(path);
+    let path = path.canonicalize().unwrap_or(path);
+    let uri = Url::from_file_path(&path)
+        .map_err(|_| format!("{} is not a valid path", path.display()))?;
+    let text =
+        std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let config = Config::load(roots).unwrap_or_default();
+    let read = |uri: &Url| std::fs::read_to_string(uri.to_file_path().ok()?).ok();
+    let linked = link(&uri, &text, roots, &read);
+    let analysis = analyze_file(&Database::default(), &uri, &linked, &config);
+    Ok((path, text, analysis))
+}
+
+// The folder of the workspace comes in the launch arguments, without it the adapter was
+// started in the folder, like the command line
+fn launch_roots(arguments: &Json) -> Vec<PathBuf> {
+    match arguments["cwd"].as_str() {
+        Some(cwd) => vec![PathBuf::from(cwd)],
+        None => std::env::current_dir().into_iter().collect(),
+    }
+}
+
+fn load_program(path: &str, roots: &[PathBuf]) -> Result<Program, String> {
+    let (path, text, analysis) = analyze_path(path, roots)?;
+    let error = analysis
+        .diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR));
+    if let Some(error) = error {
+        return Err(format!("{} has errors: {}", path.display(), error.message));
+    }
+
+    let mut modules = analysis
+        .imports
+        .into_iter()
+        .map(|module| {
+            let path = module.uri.to_file_path().unwrap_or_default();
+            Module {
+                path: path.canonicalize().unwrap_or(path),
+                rope: module.rope,
+                ast: module.ast,
+            }
+        })
+        .collect::<Vec<_>>();
+    modules.push(Module {
+        path,
+        rope: Rope::from_str(&text),
+        ast: analysis.ast,
+    });
+    Ok(Program {
+        modules,
+        type_table: analysis.type_table,
+    })
+}
+
+// The nodes the interpreter stops at: top level declarations, the expressions of a block and
+// the body of a lambda that is not a block
+fn collect_statements(node: &Anotated<Ast>, statement: bool, statements: &mut Vec<Span>) {
+    if statement && !matches!(node.0, Ast::Coment(_)) {
+        statements.push(node.1.clone());
+    }
+    match &node.0 {
+        Ast::Declaration(_, _, _, _, Some(value)) => collect_statements(value, false, statements),
+        Ast::Call(caller, args) => {
+            collect_statements(caller, false, statements);
+            for arg in args {
+                collect_statements(arg, false, statements);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            collect_statements(l, false, statements);
+            collect_statements(r, false, statements);
+        }
+        Ast::While(_, cond, body) => {
+            collect_statements(cond, false, statements);
+            collect_statements(body, false, statements);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            collect_statements(cond, false, statements);
+            collect_statements(if_body, false, statements);
+            collect_statements(else_body, false, statements);
+        }
+        Ast::Tuple(args) => {
+            for arg in args {
+                collect_statements(arg, false, statements);
+            }
+        }
+        Ast::Block(expresions) => {
+            for node in expresions {
+                collect_statements(node, true, statements);
+            }
+        }
+        Ast::Lambda(_, _, body) => {
+            let is_block = matches!(body.0, Ast::Block(_));
+            collect_statements(body, !is_block, statements);
+        }
+        _ => (),
+    }
+}
+
+// Whether running the expression assigns with `=`, which changes the binding where it was
+// declared even from a child scope
+fn assigns(node: &Anotated<Ast>) -> bool {
+    match &node.0 {
+        Ast::Declaration(_, (Token::Op(op), _), _, _, _) if op == "=" => true,
+        Ast::Declaration(_, _, _, _, value) => value.as_deref().is_some_and(assigns),
+        Ast::Call(caller, args) => assigns(caller) || args.iter().any(assigns),
+        Ast::Binary(l, _, r) => assigns(l) || assigns(r),
+        Ast::While(_, cond, body) => assigns(cond) || assigns(body),
+        Ast::If(_, cond, if_body, _, else_body) => {
+            assigns(cond) || assigns(if_body) || assigns(else_body)
+        }
+        Ast::Tuple(args) | Ast::Block(args) => args.iter().any(assigns),
+        Ast::Lambda(_, _, body) => assigns(body),
+        _ => false,
+    }
+}
+
+// Moves every requested line to the first statement that starts at it or after it
+fn set_breakpoints(shared: &Shared, roots: &[PathBuf], arguments: &Json) -> Result<Json, String> {
+    let path = arguments["source"]["path"]
+        .as_str()
+        .ok_or("setBreakpoints needs the path of the source")?;
+    let (path, text, analysis) = analyze_path(path, roots)?;
+    let rope = Rope::from_str(&text);
+
+    let mut statements = Vec::new();
+    for node in &analysis.ast {
+        if let Ast::Declaration(..) = node.0 {
+            collect_statements(node, true, &mut statements);
+        }
+    }
+    let mut lines = statements
+        .iter()
+        .filter_map(|span| rope.try_char_to_line(span.start).ok())
+        .collect::<Vec<_>>();
+    lines.sort_unstable();
+    lines.dedup();
+
+    let mut verified = Vec::new();
+    let mut breakpoints = Vec::new();
+    for requested in arguments["breakpoints"].as_array().into_iter().flatten() {
+        let line = requested["line"].as_u64().unwrap_or(1).saturating_sub(1) as usize;
+        match lines.iter().find(|statement| **statement >= line) {
+            Some(statement) => {
+                verified.push(*statement);
+                breakpoints.push(json!({ "verified": true, "line": statement + 1 }));
+            }
+            None => breakpoints.push(json!({
+                "verified": false,
+                "line": line + 1,
+                "message": "There is no statement at or after this line",
+            })),
+        }
+    }
+    shared.breakpoints.lock().unwrap().insert(path, verified);
+    Ok(json!({ "breakpoints": breakpoints }))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    // Stop at the first statement, the launch asked for it
+    Entry,
+    Continue,
+    In,
+    // Stop at a statement of the same call or of one of its callers
+    Over(usize),
+    // Stop once the call returns
+    Out(usize),
+}
+
+// Something the variables view can expand
+enum Handle<'a> {
+    Scope(Env<'a>),
+    Tuple(Vec<Value<'a>>),
+}
+
+fn stopped_error() -> RuntimeError {
+    RuntimeError {
+        message: "the debug session ended".to_string(),
+    }
+}
+
+struct Stepper<'a> {
+    program: &'a Program,
+    shared: Arc<Shared>,
+    requests: Receiver<Json>,
+    step: Step,
+    // Valid until the program runs again, variablesReference is the index plus one
+    handles: Vec<Handle<'a>>,
+    // Parsed watch expressions, they are asked again at every stop
+    watches: HashMap<String, &'a Anotated<Ast>>,
+    // Values found while evaluating can point into the expression, so the nodes live in an
+    // arena owned by run_program, freed when the program ends
+    watch_arena: &'a Arena<Anotated<Ast>>,
+}
+
+impl<'a> Stepper<'a> {
+    fn handle(&mut self, handle: Handle<'a>) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+
+    fn expandable(&mut self, value: &Value<'a>) -> usize {
+        match value {
+            Value::Tuple(members) if !members.is_empty() => {
+                self.handle(Handle::Tuple(members.clone()))
+            }
+            _ => 0,
+        }
+    }
+
+    fn stack_trace(&self, frames: &[Frame<'a>]) -> Json {
+        let stack_frames = frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                let module = &self.program.modules[frame.module];
+                let line = module.rope.try_char_to_line(frame.span.start).unwrap_or(0);
+                let column = frame.span.start - module.rope.line_to_char(line);
+                json!({
+                    "id": id,
+                    "name": frame.name,
+                    "source": {
+                        "name": module.path.file_name().map(|name| name.to_string_lossy()),
+                        "path": module.path,
+                    },
+                    "line": line + 1,
+                    "column": column + 1,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "stackFrames": stack_frames, "totalFrames": frames.len() })
+    }
+
+    // The scope chain of the frame, from the innermost block to the globals
+    fn scopes(&mut self, frames: &[Frame<'a>], arguments: &Json) -> Result<Json, String> {
+        let frame = arguments["frameId"]
+            .as_u64()
+            .and_then(|id| frames.get(id as usize))
+            .ok_or("unknown frame")?;
+        let mut chain = Vec::new();
+        let mut env = Some(frame.env.clone());
+        while let Some(scope) = env {
+            env = scope.parent.clone();
+            chain.push(scope);
+        }
+
+        let last = chain.len() - 1;
+        let scopes = chain
+            .into_iter()
+            .enumerate()
+            .map(|(i, scope)| {
+                let name = match i {
+                    _ if i == last => "Globals",
+                    0 => "Locals",
+                    _ => "Closure",
+                };
+                let reference = self.handle(Handle::Scope(scope));
+                json!({ "name": name, "variablesReference": reference, "expensive": false })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let index = arguments["variablesReference"]
+            .as_u64()
+            .and_then(|reference| (reference as usize).checked_sub(1))
+            .filter(|index| *index < self.handles.len())
+            .ok_or("unknown variables reference")?;
+
+        // (name, value, type)
+        let mut entries: Vec<(String, Value<'a>, Option<&'a Type>)> = Vec::new();
+        match &self.handles[index] {
+            Handle::Scope(scope) => {
+                // A name declared again hides the previous one
+                for var in scope.vars.borrow().iter().rev() {
+                    let builtin = matches!(var.value, Value::Builtin(_));
+                    if !builtin && !entries.iter().any(|(name, ..)| *name == var.name) {
+                        entries.push((var.name.clone(), var.value.clone(), var.ty));
+                    }
+                }
+                entries.reverse();
+            }
+            Handle::Tuple(members) => {
+                for (i, member) in members.iter().enumerate() {
+                    entries.push((i.to_string(), member.clone(), None));
+                }
+            }
+        }
+
+        let program = self.program;
+        let type_table = &program.type_table;
+        let variables = entries
+            .into_iter()
+            .map(|(name, value, ty)| {
+                let ty = ty.map(|ty| Inferer::get_most_concrete_type(ty, type_table).to_string());
+                json!({
+                    "name": name,
+                    "value": value.to_string(),
+                    "type": ty,
+                    "variablesReference": self.expandable(&value),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn watch(&mut self, expression: &str) -> Result<&'a Anotated<Ast>, String> {
+        if let Some(node) = self.watches.get(expression) {
+            return Ok(*node);
+        }
+        // parse_file also infers, without the bindings of the frame, so its type errors and
+        // unknown names are not ours. The interpreter finds the names in the frame
+        let (_, ast, errors) = parse_file(&mask_holes(expression));
+        let syntax_error = errors
+            .iter()
+            .any(|item| !matches!(item.reason(), SimpleReason::Custom(_)));
+        let node = match ast.and_then(|(ast, _)| ast.into_iter().last()) {
+            Some(node) if !syntax_error => node,
+            _ => return Err(format!("can not parse `{}`", expression)),
+        };
+        // A watch only reads the frame
+        if matches!(node.0, Ast::Declaration(..)) {
+            return Err(format!("`{}` declares a name, watches can not", expression));
+        }
+        if assigns(&node) {
+            return Err(format!(
+                "`{}` assigns to a name, watches can not",
+                expression
+            ));
+        }
+        let node: &'a Anotated<Ast> = self.watch_arena.alloc(node);
+        self.watches.insert(expression.to_string(), node);
+        Ok(node)
+    }
+
+    // Runs the expression in a child scope of the frame, on a separate interpreter so a print
+    // or an error does not touch the program. The expression can not declare or assign names,
+    // but it can call the functions of the program and whatever they assign stays assigned
+    fn evaluate(&mut self, frames: &[Frame<'a>], arguments: &Json) -> Result<Json, String> {
+        let expression = arguments["expression"]
+            .as_str()
+            .ok_or("evaluate needs an expression")?;
+        let frame = match arguments["frameId"].as_u64() {
+            Some(id) => frames.get(id as usize).ok_or("unknown frame")?,
+            None => frames.last().ok_or("unknown frame")?,
+        };
+        let node = self.watch(expression)?;
+
+        let mut evaluator = Interpreter::default();
+        let value = evaluator
+            .eval(node, &child(&frame.env))
+            .map_err(|err| err.message)?;
+        Ok(json!({
+            "result": value.to_string(),
+            "variablesReference": self.expandable(&value),
+        }))
+    }
+
+    // Answers the requests of the client until it lets the program go on
+    fn pause(&mut self, reason: &str, frames: &[Frame<'a>]) -> Result<(), RuntimeError> {
+        self.handles.clear();
+        self.shared.paused.store(true, Ordering::Relaxed);
+        self.shared.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        );
+
+        loop {
+            let request = self.requests.recv().map_err(|_| stopped_error())?;
+            let arguments = &request["arguments"];
+            let step = match request["command"].as_str().unwrap_or("") {
+                "continue" => Some(Step::Continue),
+                "next" => Some(Step::Over(frames.len())),
+                "stepIn" => Some(Step::In),
+                "stepOut" => Some(Step::Out(frames.len())),
+                _ => None,
+            };
+            if let Some(step) = step {
+                self.step = step;
+                self.shared
+                    .respond(&request, Ok(json!({ "allThreadsContinued": true })));
+                return Ok(());
+            }
+
+            let result = match request["command"].as_str().unwrap_or("") {
+                "stackTrace" => Ok(self.stack_trace(frames)),
+                "scopes" => self.scopes(frames, arguments),
+                "variables" => self.variables(arguments),
+                "evaluate" => self.evaluate(frames, arguments),
+                command => Err(format!("{} is not supported", command)),
+            };
+            self.shared.respond(&request, result);
+        }
+    }
+}
+
+impl<'a> Debugger<'a> for Stepper<'a> {
+    fn statement(
+        &mut self,
+        node: &'a Anotated<Ast>,
+        frames: &[Frame<'a>],
+    ) -> Result<(), RuntimeError> {
+        if self.shared.stopped.load(Ordering::Relaxed) {
+            return Err(stopped_error());
+        }
+
+        let depth = frames.len();
+        let reason = match self.step {
+            Step::Entry => Some("entry"),
+            Step::In => Some("step"),
+            Step::Over(from) if depth <= from => Some("step"),
+            Step::Out(from) if depth < from => Some("step"),
+            _ => None,
+        };
+        let reason = reason.or_else(|| {
+            let module = &self.program.modules[frames.last()?.module];
+            let line = module.rope.try_char_to_line(node.1.start).ok()?;
+            if self.shared.is_breakpoint(&module.path, line) {
+                Some("breakpoint")
+            } else {
+                None
+            }
+        });
+        match reason {
+            Some(reason) => self.pause(reason, frames),
+            None => Ok(()),
+        }
+    }
+
+    fn print(&mut self, line: &str) {
+        self.shared.output("stdout", format!("{}\n", line));
+    }
+}
+
+// Loads every module and calls `main` if the program has one
+fn run_program(
+    program: Program,
+    shared: Arc<Shared>,
+    requests: Receiver<Json>,
+    stop_on_entry: bool,
+) {
+    // Declared before the interpreter so it is dropped after it
+    let watch_arena = Arena::new();
+    let mut interpreter = Interpreter::default();
+    interpreter.debugger = Some(Box::new(Stepper {
+        program: &program,
+        shared: shared.clone(),
+        requests,
+        step: if stop_on_entry {
+            Step::Entry
+        } else {
+            Step::Continue
+        },
+        handles: Vec::new(),
+        watches: HashMap::new(),
+        watch_arena: &watch_arena,
+    }));
+
+    let mut run = || -> Result<Option<String>, RuntimeError> {
+        for (index, module) in program.modules.iter().enumerate() {
+            interpreter.load(index, &module.ast)?;
+        }
+        match lookup(&interpreter.globals, "main") {
+            Some(main @ Value::Closure(_)) => {
+                Ok(Some(interpreter.call(main, vec![], "main")?.to_string()))
+            }
+            _ => Ok(None),
+        }
+    };
+    let exit_code = match run() {
+        Ok(result) => {
+            if let Some(result) = result {
+                shared.output("console", format!("=> {}\n", result));
+            }
+            0
+        }
+        Err(_) if shared.stopped.load(Ordering::Relaxed) => 0,
+        Err(err) => {
+            shared.output("stderr", format!("runtime error: {}\n", err.message));
+            1
+        }
+    };
+    shared.event("exited", json!({ "exitCode": exit_code }));
+    shared.event("terminated", json!({}));
+}
+
+#[derive(Default)]
+struct Session {
+    // Loaded by launch, it moves to the interpreter thread once the client is configured
+    program: Option<Program>,
+    stop_on_entry: bool,
+    // Where imports and the config are found, known since the launch
+    roots: Vec<PathBuf>,
+    configured: bool,
+    // Requests that need the paused program go to its thread
+    runner: Option<Sender<Json>>,
+}
+
+impl Session {
+    fn start(&mut self, shared: &Arc<Shared>) {
+        if !self.configured || self.runner.is_some() {
+            return;
+        }
+        if let Some(program) = self.program.take() {
+            let (sender, receiver) = channel();
+            let shared = shared.clone();
+            let stop_on_entry = self.stop_on_entry;
+            thread::spawn(move || run_program(program, shared, receiver, stop_on_entry));
+            self.runner = Some(sender);
+        }
+    }
+
+    // false once the client disconnects
+    fn handle(&mut self, request: Json, shared: &Arc<Shared>) -> bool {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                shared.respond(
+                    &request,
+                    Ok(json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsEvaluateForHovers": true,
+                        "supportsTerminateRequest": true,
+                    })),
+                );
+            }
+            "launch" => {
+                self.roots = launch_roots(arguments);
+                let program = arguments["program"]
+                    .as_str()
+                    .ok_or_else(|| "launch needs a program".to_string())
+                    .and_then(|path| load_program(path, &self.roots));
+                match program {
+                    Ok(program) => {
+                        self.program = Some(program);
+                        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                        shared.respond(&request, Ok(json!({})));
+                        // The breakpoints come after this, once the roots are known
+                        shared.event("initialized", json!({}));
+                        self.start(shared);
+                    }
+                    Err(message) => shared.respond(&request, Err(message)),
+                }
+            }
+            "setBreakpoints" => {
+                shared.respond(&request, set_breakpoints(shared, &self.roots, arguments))
+            }
+            "setExceptionBreakpoints" => shared.respond(&request, Ok(json!({}))),
+            "configurationDone" => {
+                self.configured = true;
+                shared.respond(&request, Ok(json!({})));
+                self.start(shared);
+            }
+            "threads" => shared.respond(
+                &request,
+                Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            ),
+            "pause" => shared.respond(
+                &request,
+                Err("a running program can only stop at a breakpoint".to_string()),
+            ),
+            command @ ("disconnect" | "terminate") => {
+                // A paused program sees the channel closed, a running one stops at the next statement
+                shared.stopped.store(true, Ordering::Relaxed);
+                self.runner = None;
+                shared.respond(&request, Ok(json!({})));
+                return command != "disconnect";
+            }
+            command => {
+                // Until the next stop nobody would answer, the client can not wait for it
+                if self.runner.is_some() && !shared.paused.load(Ordering::Relaxed) {
+                    shared.respond(
+                        &request,
+                        Err(format!("{} needs the program to be paused", command)),
+                    );
+                    return true;
+                }
+                // Marked here and not by the thread, so a request sent right after is not queued
+                if matches!(command, "continue" | "next" | "stepIn" | "stepOut") {
+                    shared.paused.store(false, Ordering::Relaxed);
+                }
+                let sent = match &self.runner {
+                    Some(runner) => runner.send(request.clone()).is_ok(),
+                    None => false,
+                };
+                if !sent {
+                    shared.respond(&request, Err("the program is not running".to_string()));
+                }
+            }
+        }
+        true
+    }
+}
+
+// Serves a debug session on stdio until the client disconnects. Lines and columns are 1 based
+pub fn run() {
+    let shared = Arc::new(Shared {
+        output: Mutex::new(io::stdout()),
+        seq: AtomicI64::new(0),
+        breakpoints: Mutex::new(HashMap::new()),
+        stopped: AtomicBool::new(false),
+        paused: AtomicBool::new(false),
+    });
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut session = Session::default();
+    while let Some(request) = read_message(&mut reader) {
+        if !session.handle(request, &shared) {
+            break;
+        }
+    }
+}
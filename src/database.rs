@@ -0,0 +1,594 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chumsky::error::{Simple, SimpleReason};
+use chumsky::Error;
+use dashmap::DashMap;
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    parse_file,
+    token::{Span, Spanned, Token},
+    types::Type,
+};
+use tower_lsp::lsp_types::{Diagnostic, Url};
+
+use crate::config::Config;
+use crate::holes::mask_holes;
+use crate::imports::{ImportedModule, Linked};
+use crate::scope::{self, Scopes};
+use crate::types::shift_variables;
+
+// Statements we keep, the one used the longest time ago goes first
+const MAX_STATEMENTS: usize = 4096;
+
+// What the frontend and the lints say about one text of a file, already split from its imports
+#[derive(Debug)]
+pub struct Analysis {
+    pub tokens: Vec<Spanned<Token>>,
+    // Only the statements that could be parsed
+    pub ast: Vec<Anotated<Ast>>,
+    pub type_table: Vec<Type>,
+    // Files pulled in by the imports, they were inferred together with the document
+    pub imports: Vec<ImportedModule>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// What the frontend says about one top level statement, inferred after the statements it uses.
+// Spans count from the start of the statement and the types index its own table
+#[derive(Debug)]
+struct Statement {
+    tokens: Vec<Spanned<Token>>,
+    // False if the frontend gave no tree, the ast is empty then
+    parsed: bool,
+    ast: Vec<Anotated<Ast>>,
+    type_table: Vec<Type>,
+    errors: Vec<Simple<String>>,
+}
+
+impl Statement {
+    fn has_syntax_errors(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|error| !matches!(error.reason(), SimpleReason::Custom(_)))
+    }
+
+    // Cut before its end, like `x := 1 +` with the rest in the next line
+    fn is_unfinished(&self) -> bool {
+        self.errors.iter().any(|error| {
+            matches!(error.reason(), SimpleReason::Unexpected) && error.found().is_none()
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    statement: Arc<Statement>,
+    used: AtomicUsize,
+}
+
+// Memo of the top level statements of every file. The key is the text of the statement and the
+// text of the statements it uses, so an entry never goes stale and an edit only infers again
+// the statement that changed and the ones that use it
+#[derive(Debug, Default)]
+pub struct Database {
+    statements: DashMap<u64, Entry>,
+    clock: AtomicUsize,
+}
+
+impl Database {
+    fn get(&self, key: u64) -> Option<Arc<Statement>> {
+        let entry = self.statements.get(&key)?;
+        entry.used.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        Some(entry.statement.clone())
+    }
+
+    fn insert(&self, key: u64, statement: Statement) -> Arc<Statement> {
+        if self.statements.len() >= MAX_STATEMENTS {
+            let oldest = self
+                .statements
+                .iter()
+                .min_by_key(|entry| entry.used.load(Ordering::Relaxed))
+                .map(|entry| *entry.key());
+            if let Some(oldest) = oldest {
+                self.statements.remove(&oldest);
+            }
+        }
+        let statement = Arc::new(statement);
+        self.statements.insert(
+            key,
+            Entry {
+                statement: statement.clone(),
+                used: AtomicUsize::new(self.clock.fetch_add(1, Ordering::Relaxed)),
+            },
+        );
+        statement
+    }
+
+    // The statement sources[target], inferred with the other sources around it as they are in
+    // the file. The frontend only takes whole texts, so they go to it one after the other
+    fn query(&self, sources: &[&str], target: usize) -> Arc<Statement> {
+        let mut hasher = DefaultHasher::new();
+        sources.hash(&mut hasher);
+        target.hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(statement) = self.get(key) {
+            return statement;
+        }
+
+        let start = sources[..target]
+            .iter()
+            .map(|source| source.chars().count())
+            .sum::<usize>();
+        let end = start + sources[target].chars().count();
+        let is_last = target + 1 == sources.len();
+        let inside = |span: &Span| start <= span.start && (span.start < end || is_last);
+
+        let (tokens, ast_and_type_table, errors) = parse_file(&sources.concat());
+        let parsed = ast_and_type_table.is_some();
+        let (ast, type_table) = ast_and_type_table.unwrap_or_default();
+        let statement = Statement {
+            tokens: tokens
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, span)| inside(span))
+                .map(|(token, span)| (token, move_span(&span, start, 0)))
+                .collect(),
+            parsed,
+            ast: ast
+                .into_iter()
+                .filter(|node| inside(&node.1))
+                .map(|mut node| {
+                    move_node(&mut node, start, 0, 0);
+                    node
+                })
+                .collect(),
+            type_table,
+            errors: errors
+                .iter()
+                .filter(|error| inside(&error.span()))
+                .map(|error| move_error(error, start, 0))
+                .collect(),
+        };
+        self.insert(key, statement)
+    }
+}
+
+// Top level statements begin at the start of a line, out of any bracket, string or comment.
+// Lines that begin with a blank belong to the statement above
+fn split_statements(chars: &[char]) -> Vec<Range<usize>> {
+    let mut starts = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut escaped = false;
+    for (i, c) in chars.iter().enumerate() {
+        let line_start = i == 0 || chars[i - 1] == '\n';
+        if line_start && (i == 0 || depth == 0 && !in_string && !c.is_whitespace()) {
+            starts.push(i);
+        }
+        if in_comment {
+            in_comment = *c != '\n';
+            continue;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '/' if chars.get(i + 1) == Some(&'/') => in_comment = true,
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, start)| *start..starts.get(i + 1).copied().unwrap_or(chars.len()))
+        .collect()
+}
+
+// Names a statement needs from the others: the ones it uses without declaring them, and the ones
+// it assigns with `=`, which change a binding declared before
+fn needed_names(statement: &Statement, scopes: &Scopes) -> Vec<(String, bool)> {
+    let mut names = scopes
+        .unresolved
+        .iter()
+        .map(|(name, _)| (name.clone(), false))
+        .collect::<Vec<_>>();
+    for node in &statement.ast {
+        if let Ast::Declaration(pattern, (Token::Op(op), _), None, _, _) = &node.0 {
+            if let (Pattern::Var((name, _)), "=") = (&pattern.0, op.as_str()) {
+                names.push((name.clone(), true));
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn declares(scopes: &Scopes, name: &str) -> bool {
+    scopes
+        .bindings
+        .iter()
+        .any(|binding| binding.scope.is_none() && binding.name == name)
+}
+
+// Statements each one depends on: for every name, the last one before it that declares the name.
+// A use with nothing before may still be declared after, like a function that calls a later one
+fn find_dependencies(statements: &[Arc<Statement>]) -> Vec<Vec<usize>> {
+    let scopes = statements
+        .iter()
+        .map(|statement| scope::resolve(&statement.ast))
+        .collect::<Vec<_>>();
+    statements
+        .iter()
+        .enumerate()
+        .map(|(i, statement)| {
+            let mut dependencies = needed_names(statement, &scopes[i])
+                .into_iter()
+                .filter_map(|(name, assigned)| {
+                    let before = (0..i).rev().find(|other| declares(&scopes[*other], &name));
+                    if assigned {
+                        return before;
+                    }
+                    before.or_else(|| {
+                        (i + 1..statements.len()).find(|other| declares(&scopes[*other], &name))
+                    })
+                })
+                .collect::<Vec<_>>();
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies
+        })
+        .collect()
+}
+
+// The statement and everything it depends on, even through others, in the order of the file
+fn with_dependencies(dependencies: &[Vec<usize>], index: usize) -> Vec<usize> {
+    let mut found = vec![index];
+    let mut pending = vec![index];
+    while let Some(next) = pending.pop() {
+        for dependency in &dependencies[next] {
+            if !found.contains(dependency) {
+                found.push(*dependency);
+                pending.push(*dependency);
+            }
+        }
+    }
+    found.sort_unstable();
+    found
+}
+
+// Parsing, inference and lints of a file with its imports. The editor and the command line
+// both go through here so they always agree. Each top level statement is inferred on its own,
+// after the statements it uses, and the database keeps the result while those do not change
+pub fn analyze_file(database: &Database, uri: &Url, linked: &Linked, config: &Config) -> Analysis {
+    let chars = mask_holes(&linked.text).chars().collect::<Vec<_>>();
+    let text_of = |range: &Range<usize>| chars[range.clone()].iter().collect::<String>();
+
+    // The split only looks at the text, the frontend says whether it was right
+    let mut ranges = split_statements(&chars);
+    let mut alone: Vec<Arc<Statement>> = Vec::new();
+    let mut i = 0;
+    while i < ranges.len() {
+        let statement = database.query(&[&text_of(&ranges[i])], 0);
+        if statement.is_unfinished() && i + 1 < ranges.len() {
+            ranges[i].end = ranges.remove(i + 1).end;
+            continue;
+        }
+        // One that can not go on its own, like an `else` in a new line, may go with the one above
+        if i > 0 && statement.has_syntax_errors() {
+            let together = database.query(&[&text_of(&(ranges[i - 1].start..ranges[i].end))], 0);
+            if !together.has_syntax_errors() {
+                ranges[i - 1].end = ranges.remove(i).end;
+                alone[i - 1] = together;
+                continue;
+            }
+        }
+        alone.push(statement);
+        i += 1;
+    }
+
+    let texts = ranges.iter().map(text_of).collect::<Vec<_>>();
+    let dependencies = find_dependencies(&alone);
+    let mut tokens = Vec::new();
+    let mut ast = Vec::new();
+    let mut type_table: Vec<Type> = Vec::new();
+    let mut errors = Vec::new();
+    let mut parsed = true;
+    for (index, range) in ranges.iter().enumerate() {
+        let context = with_dependencies(&dependencies, index);
+        let statement = if context.len() == 1 {
+            alone[index].clone()
+        } else {
+            let sources = context
+                .iter()
+                .map(|other| texts[*other].as_str())
+                .collect::<Vec<_>>();
+            let target = context.iter().position(|other| *other == index).unwrap();
+            database.query(&sources, target)
+        };
+
+        // Back to the place of the statement in the file, after the types of the ones before
+        let types = type_table.len();
+        type_table.extend(
+            statement
+                .type_table
+                .iter()
+                .map(|ty| shift_variables(ty, types)),
+        );
+        tokens.extend(
+            statement
+                .tokens
+                .iter()
+                .map(|(token, span)| (token.clone(), move_span(span, 0, range.start))),
+        );
+        ast.extend(statement.ast.iter().map(|node| {
+            let mut node = node.clone();
+            move_node(&mut node, 0, range.start, types);
+            node
+        }));
+        errors.extend(
+            statement
+                .errors
+                .iter()
+                .map(|error| move_error(error, 0, range.start)),
+        );
+        parsed &= statement.parsed;
+    }
+
+    // The lints need the whole file, with a statement missing its names would look unused
+    let diagnostics = linked.diagnostics(
+        config,
+        uri,
+        &errors,
+        parsed.then_some(ast.as_slice()),
+        &type_table,
+    );
+
+    // Every span goes back to the file it came from
+    let (ast, imports) = linked.split_ast(ast);
+    Analysis {
+        tokens: linked.split_tokens(tokens),
+        ast,
+        type_table,
+        imports,
+        diagnostics,
+    }
+}
+
+fn move_span(span: &Span, from: usize, to: usize) -> Span {
+    span.start.saturating_sub(from) + to..span.end.saturating_sub(from) + to
+}
+
+fn move_type(ty: &mut Option<Type>, types: usize) {
+    if let Some(ty) = ty {
+        *ty = shift_variables(ty, types);
+    }
+}
+
+fn move_pattern(pattern: &mut Anotated<Pattern>, from: usize, to: usize, types: usize) {
+    pattern.1 = move_span(&pattern.1, from, to);
+    move_type(&mut pattern.2, types);
+    match &mut pattern.0 {
+        Pattern::Var((_, span)) => *span = move_span(span, from, to),
+        Pattern::Tuple(args) => {
+            for arg in args {
+                move_pattern(arg, from, to, types);
+            }
+        }
+    }
+}
+
+// Spans from one place of a text to another, and type variables after the ones of other tables
+fn move_node(node: &mut Anotated<Ast>, from: usize, to: usize, types: usize) {
+    node.1 = move_span(&node.1, from, to);
+    move_type(&mut node.2, types);
+    match &mut node.0 {
+        Ast::Error => (),
+        Ast::Type(ty) => *ty = shift_variables(ty, types),
+        Ast::Literal((_, span)) | Ast::Variable((_, span)) | Ast::Coment((_, span)) => {
+            *span = move_span(span, from, to)
+        }
+        Ast::Declaration(pattern, (_, span), ty, _, value) => {
+            move_pattern(pattern, from, to, types);
+            *span = move_span(span, from, to);
+            if let Some(ty) = ty {
+                move_node(ty, from, to, types);
+            }
+            if let Some(value) = value {
+                move_node(value, from, to, types);
+            }
+        }
+        Ast::Call(caller, args) => {
+            move_node(caller, from, to, types);
+            for arg in args {
+                move_node(arg, from, to, types);
+            }
+        }
+        Ast::Binary(l, (_, span), r) => {
+            move_node(l, from, to, types);
+            *span = move_span(span, from, to);
+            move_node(r, from, to, types);
+        }
+        Ast::While((_, span), cond, body) => {
+            *span = move_span(span, from, to);
+            move_node(cond, from, to, types);
+            move_node(body, from, to, types);
+        }
+        Ast::If((_, span), cond, if_body, else_tk, else_body) => {
+            *span = move_span(span, from, to);
+            move_node(cond, from, to, types);
+            move_node(if_body, from, to, types);
+            if let Some((_, span)) = else_tk {
+                *span = move_span(span, from, to);
+            }
+            move_node(else_body, from, to, types);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                move_node(arg, from, to, types);
+            }
+        }
+        Ast::Lambda(args, (_, span), body) => {
+            for arg in args {
+                move_node(arg, from, to, types);
+            }
+            *span = move_span(span, from, to);
+            move_node(body, from, to, types);
+        }
+    }
+}
+
+// Errors can not change their span, so a new one is made with the tokens as text
+fn move_error<T: std::fmt::Display + Hash + Eq>(
+    error: &Simple<T>,
+    from: usize,
+    to: usize,
+) -> Simple<String> {
+    let span = move_span(&error.span(), from, to);
+    let found = error.found().map(|token| token.to_string());
+    let mut expected = error
+        .expected()
+        .map(|token| token.as_ref().map(|token| token.to_string()))
+        .collect::<Vec<_>>();
+    let moved = match error.reason() {
+        SimpleReason::Custom(message) => Simple::custom(span, message),
+        SimpleReason::Unclosed {
+            span: opened,
+            delimiter,
+        } => Simple::unclosed_delimiter(
+            move_span(opened, from, to),
+            delimiter.to_string(),
+            span,
+            expected.pop().flatten().unwrap_or_default(),
+            found,
+        ),
+        SimpleReason::Unexpected => Simple::expected_input_found(span, expected, found),
+    };
+    match error.label() {
+        Some(label) => moved.with_label(label),
+        None => moved,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imports::link;
+
+    fn analyze(database: &Database, text: &str) -> Analysis {
+        let uri = Url::parse("file:///test.lang").unwrap();
+        let linked = link(&uri, text, &[], &|_| None);
+        analyze_file(database, &uri, &linked, &Config::default())
+    }
+
+    fn statements(text: &str) -> Vec<String> {
+        let chars = text.chars().collect::<Vec<_>>();
+        split_statements(&chars)
+            .into_iter()
+            .map(|range| chars[range].iter().collect())
+            .collect()
+    }
+
+    fn empty_statement() -> Statement {
+        Statement {
+            tokens: Vec::new(),
+            parsed: true,
+            ast: Vec::new(),
+            type_table: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn statements_start_out_of_brackets_strings_and_comments() {
+        let text = "a := 1\n\nf := () -> {\n    b := 2\n}\n// note {\ns := \"x\n{\"\nc := (1,\n2)";
+        assert_eq!(
+            statements(text),
+            vec![
+                "a := 1\n\n",
+                "f := () -> {\n    b := 2\n}\n",
+                "// note {\n",
+                "s := \"x\n{\"\n",
+                "c := (1,\n2)",
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_are_inferred_after_the_ones_they_use() {
+        let text = "a := 1\nb := a\nc := missing\n";
+        let analysis = analyze(&Database::default(), text);
+        let b = analysis
+            .ast
+            .iter()
+            .find_map(|node| match &node.0 {
+                Ast::Declaration(pattern, ..) if node.1.start == 7 => pattern.2.clone(),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(b, Type::Int);
+        let errors = analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![(2, "Undefined variable missing")]);
+    }
+
+    #[test]
+    fn spans_point_to_the_file() {
+        let text = "a := 1\nb := a\n";
+        let analysis = analyze(&Database::default(), text);
+        let spans = analysis
+            .tokens
+            .iter()
+            .map(|(_, span)| span.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![0..1, 2..4, 5..6, 7..8, 9..11, 12..13]);
+        assert_eq!(analysis.ast[1].1, 7..13);
+    }
+
+    #[test]
+    fn an_edit_only_infers_again_what_changed() {
+        let database = Database::default();
+        // a, b and c on their own, and b after a
+        analyze(&database, "a := 1\nb := a\nc := 2\n");
+        assert_eq!(database.statements.len(), 4);
+        analyze(&database, "a := 1\nb := a\nc := 3\n");
+        assert_eq!(database.statements.len(), 5);
+        // b uses a, so it is inferred again
+        analyze(&database, "a := 5\nb := a\nc := 3\n");
+        assert_eq!(database.statements.len(), 7);
+        analyze(&database, "a := 1\nb := a\nc := 2\n");
+        assert_eq!(database.statements.len(), 7);
+    }
+
+    #[test]
+    fn the_least_recently_used_statement_goes_first() {
+        let database = Database::default();
+        for key in 0..MAX_STATEMENTS as u64 {
+            database.insert(key, empty_statement());
+        }
+        assert!(database.get(0).is_some());
+        database.insert(MAX_STATEMENTS as u64, empty_statement());
+        assert_eq!(database.statements.len(), MAX_STATEMENTS);
+        assert!(database.get(0).is_some());
+        assert!(database.get(1).is_none());
+    }
+}
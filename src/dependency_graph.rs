@@ -0,0 +1,37 @@
+use std::collections::{HashMap, HashSet};
+
+use tower_lsp::lsp_types::Url;
+
+// The files each file imports, so a change only re-checks the files that depend on it
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    imports: HashMap<Url, Vec<Url>>,
+}
+
+impl DependencyGraph {
+    pub fn set_imports(&mut self, uri: &Url, imports: Vec<Url>) {
+        if imports.is_empty() {
+            self.imports.remove(uri);
+        } else {
+            self.imports.insert(uri.clone(), imports);
+        }
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.imports.remove(uri);
+    }
+
+    // Every file that imports uri, directly or through other files
+    pub fn dependents(&self, uri: &Url) -> Vec<Url> {
+        let mut found = HashSet::new();
+        let mut pending = vec![uri];
+        while let Some(current) = pending.pop() {
+            for (file, imports) in &self.imports {
+                if imports.contains(current) && file != uri && found.insert(file.clone()) {
+                    pending.push(file);
+                }
+            }
+        }
+        found.into_iter().collect()
+    }
+}
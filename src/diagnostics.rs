@@ -0,0 +1,428 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use chumsky::error::{Simple, SimpleReason};
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    types::Type,
+};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CodeDescription, Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag,
+    Location, NumberOrString, TextDocumentIdentifier, Url,
+};
+
+use crate::config::{find_ignored, is_ignored, Config};
+use crate::holes::{check_holes, find_holes};
+use crate::lints::{check_bindings, check_module, Warning};
+use crate::quick_fixes::{parse_error_fixes, type_mismatch_fixes};
+use crate::{scope, span_to_range};
+
+// Every diagnostic we produce says it comes from us
+pub const SOURCE: &str = "lang";
+
+// Explanation of every error code and lint, each one has its own section. It goes inside the
+// binary so it always matches its codes, the extension shows it as a read only document
+pub const DIAGNOSTICS_DOC: &str = include_str!("../docs/diagnostics.md");
+const DIAGNOSTICS_DOC_URI: &str = "lang-debug:diagnostics.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnclosedDelimiter,
+    UnexpectedToken,
+    UnexpectedEnd,
+    InvalidProgram,
+    UnresolvedImport,
+    ImportHasErrors,
+}
+
+impl ErrorCode {
+    // Stable codes, never reuse or renumber them
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::UnclosedDelimiter => "E0001",
+            ErrorCode::UnexpectedToken => "E0002",
+            ErrorCode::UnexpectedEnd => "E0003",
+            ErrorCode::InvalidProgram => "E0004",
+            ErrorCode::UnresolvedImport => "E0005",
+            ErrorCode::ImportHasErrors => "E0006",
+        }
+    }
+}
+
+// Link to the section of the diagnostics document that explains the code
+pub fn code_description(code: &str) -> Option<CodeDescription> {
+    let mut href = Url::parse(DIAGNOSTICS_DOC_URI).ok()?;
+    href.set_fragment(Some(&code.to_lowercase()));
+    Some(CodeDescription { href })
+}
+
+// The text of the section that explains the code, for output read outside the editor
+pub fn code_explanation(code: &str) -> Option<String> {
+    let heading = format!("## {}", code);
+    let section = DIAGNOSTICS_DOC
+        .lines()
+        .skip_while(|line| line.trim() != heading)
+        .skip(1)
+        .take_while(|line| !line.starts_with("## "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let section = section.trim();
+    if section.is_empty() {
+        None
+    } else {
+        Some(section.to_string())
+    }
+}
+
+// Transformamos un error del parser en un diagnostico que VS Code puede usar
+pub fn make_diagnostic<T: std::fmt::Display + Hash + Eq>(
+    item: &Simple<T>,
+    uri: &Url,
+    rope: &Rope,
+) -> Option<Diagnostic> {
+    let (code, message, related) = match item.reason() {
+        SimpleReason::Unclosed { span, delimiter } => (
+            ErrorCode::UnclosedDelimiter,
+            format!("Unclosed delimiter {}", delimiter),
+            Some((span.clone(), format!("{} opened here", delimiter))),
+        ),
+        SimpleReason::Unexpected => (
+            if item.found().is_some() {
+                ErrorCode::UnexpectedToken
+            } else {
+                ErrorCode::UnexpectedEnd
+            },
+            format!(
+                "{}, expected {}",
+                if item.found().is_some() {
+                    "Unexpected token in input"
+                } else {
+                    "Unexpected end of input"
+                },
+                if item.expected().len() == 0 {
+                    "something else".to_string()
+                } else {
+                    item.expected()
+                        .map(|expected| match expected {
+                            Some(expected) => expected.to_string(),
+                            None => "end of input".to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            ),
+            None,
+        ),
+        SimpleReason::Custom(msg) => (ErrorCode::InvalidProgram, msg.to_string(), None),
+    };
+
+    let related_information = match related {
+        Some((span, message)) => Some(vec![DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), span_to_range(&span, rope)?),
+            message,
+        }]),
+        None => None,
+    };
+
+    // Guardamos los arreglos en el diagnostico para que code_action los pueda usar
+    let fixes = parse_error_fixes(item, rope);
+    let data = if fixes.is_empty() {
+        None
+    } else {
+        serde_json::to_value(fixes).ok()
+    };
+
+    Some(Diagnostic {
+        range: span_to_range(&item.span(), rope)?,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(code.code().to_string())),
+        code_description: code_description(code.code()),
+        source: Some(SOURCE.to_string()),
+        message,
+        related_information,
+        data,
+        ..Diagnostic::default()
+    })
+}
+
+// Once the parser fails the errors that follow are usually caused by the first one
+fn dedup_errors<T: Hash + Eq>(errors: &[Simple<T>]) -> Vec<&Simple<T>> {
+    let mut sorted = errors.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|item| (item.span().start, item.span().end));
+
+    let mut kept: Vec<&Simple<T>> = Vec::new();
+    for item in sorted {
+        let cascades = kept.last().is_some_and(|last| {
+            let last_span = last.span();
+            let span = item.span();
+            span.start == last_span.start || span.start < last_span.end
+        });
+        let same_delimiter = match item.reason() {
+            SimpleReason::Unclosed { span, .. } => kept.iter().any(|kept| {
+                matches!(kept.reason(), SimpleReason::Unclosed { span: kept_span, .. } if kept_span == span)
+            }),
+            _ => false,
+        };
+        if !cascades && !same_delimiter {
+            kept.push(item);
+        }
+    }
+    kept
+}
+
+pub fn make_lint_diagnostic(
+    warning: &Warning,
+    severity: DiagnosticSeverity,
+    uri: &Url,
+    rope: &Rope,
+) -> Option<Diagnostic> {
+    let related_information = match &warning.related {
+        Some((span, message)) => Some(vec![DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), span_to_range(span, rope)?),
+            message: message.clone(),
+        }]),
+        None => None,
+    };
+    Some(Diagnostic {
+        range: span_to_range(&warning.span, rope)?,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(warning.lint.code().to_string())),
+        code_description: code_description(warning.lint.code()),
+        source: Some(SOURCE.to_string()),
+        message: warning.message.clone(),
+        related_information,
+        tags: if warning.lint.is_unnecessary() {
+            Some(vec![DiagnosticTag::UNNECESSARY])
+        } else {
+            None
+        },
+        ..Diagnostic::default()
+    })
+}
+
+// Parse errors plus the warnings of every lint enabled in the config. The text can be several
+// files one after the other, file_starts has the offset where each one after the first starts
+pub fn get_file_diagnostics<T: std::fmt::Display + Hash + Eq>(
+    config: &Config,
+    uri: &Url,
+    errors: &[Simple<T>],
+    ast: Option<&[Anotated<Ast>]>,
+    type_table: &[Type],
+    rope: &Rope,
+    file_starts: &[usize],
+) -> Vec<Diagnostic> {
+    let mismatches = ast.map_or_else(Vec::new, |ast| type_mismatch_fixes(ast, type_table, rope));
+    // The frontend sees holes as variables nobody declared, the typed-hole info already covers them
+    let holes = find_holes(&rope.to_string());
+    let mut diagnostics = dedup_errors(errors)
+        .into_iter()
+        .filter(|item| {
+            let span = item.span();
+            let on_hole = holes
+                .iter()
+                .any(|hole| hole.start <= span.start && span.end <= hole.end);
+            !(on_hole && matches!(item.reason(), SimpleReason::Custom(_)))
+        })
+        .filter_map(|item| {
+            let mut diagnostic = make_diagnostic(item, uri, rope)?;
+            // A type error gets the fixes of the mismatched values inside its span
+            if let SimpleReason::Custom(_) = item.reason() {
+                let span = item.span();
+                let fixes = mismatches
+                    .iter()
+                    .filter(|(value, _)| value.start < span.end && span.start < value.end)
+                    .flat_map(|(_, fixes)| fixes.iter().cloned())
+                    .collect::<Vec<_>>();
+                if !fixes.is_empty() {
+                    diagnostic.data = serde_json::to_value(fixes).ok();
+                }
+            }
+            Some(diagnostic)
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(ast) = ast {
+        let mut warnings = Vec::new();
+        let scopes = scope::resolve(ast);
+        check_bindings(&scopes, &mut warnings);
+        // A file that never ends does not make the next one unreachable
+        let file_of =
+            |node: &Anotated<Ast>| file_starts.partition_point(|start| *start <= node.1.start);
+        let mut rest = ast;
+        while let Some(first) = rest.first() {
+            let file = file_of(first);
+            let len = rest.iter().take_while(|node| file_of(node) == file).count();
+            check_module(&rest[..len], &mut warnings);
+            rest = &rest[len..];
+        }
+        check_holes(ast, &scopes, type_table, rope, &mut warnings);
+        diagnostics.extend(warnings.iter().filter_map(|warning| {
+            let severity = config.lint_level(warning.lint).severity()?;
+            make_lint_diagnostic(warning, severity, uri, rope)
+        }));
+    }
+
+    let ignored = find_ignored(ast, rope);
+    diagnostics.retain(|diagnostic| !is_ignored(diagnostic, &ignored));
+
+    diagnostics
+}
+
+// Identifies a version of the text and the config, if they do not change neither do the diagnostics
+pub fn result_id(text: &str, config_generation: usize) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    config_generation.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+// Every .lang file inside dir, skipping hidden and build folders
+pub fn find_lang_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = if let Ok(entries) = std::fs::read_dir(dir) {
+        entries
+    } else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with('.') || name == "target" || name == "node_modules")
+            .unwrap_or(false);
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_lang_files(&path, files);
+        } else if path.extension().map(|ext| ext == "lang").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+// Types of the LSP 3.17 pull model, our version of tower-lsp does not have them yet
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticParams {
+    pub text_document: TextDocumentIdentifier,
+    pub identifier: Option<String>,
+    pub previous_result_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DocumentDiagnosticReport {
+    #[serde(rename_all = "camelCase")]
+    Full {
+        result_id: Option<String>,
+        items: Vec<Diagnostic>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Unchanged { result_id: String },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousResultId {
+    pub uri: Url,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosticParams {
+    pub identifier: Option<String>,
+    pub previous_result_ids: Vec<PreviousResultId>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDocumentDiagnosticReport {
+    pub uri: Url,
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    pub report: DocumentDiagnosticReport,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDiagnosticReport {
+    pub items: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+    use crate::lints::Lint;
+
+    #[test]
+    fn every_code_is_explained() {
+        let errors = [
+            ErrorCode::UnclosedDelimiter,
+            ErrorCode::UnexpectedToken,
+            ErrorCode::UnexpectedEnd,
+            ErrorCode::InvalidProgram,
+            ErrorCode::UnresolvedImport,
+            ErrorCode::ImportHasErrors,
+        ];
+        let lints = [
+            Lint::UnusedVariable,
+            Lint::UnusedParameter,
+            Lint::Shadowing,
+            Lint::UnreachableCode,
+            Lint::ConstantCondition,
+            Lint::InfiniteLoop,
+            Lint::TypedHole,
+        ];
+        let codes = errors
+            .iter()
+            .map(ErrorCode::code)
+            .chain(lints.iter().map(Lint::code));
+        for code in codes {
+            assert!(
+                code_explanation(code).is_some(),
+                "{} is not explained",
+                code
+            );
+            let href = code_description(code).unwrap().href;
+            assert_eq!(href.fragment(), Some(code.to_lowercase().as_str()));
+        }
+        assert!(code_explanation("E9999").is_none());
+    }
+
+    #[test]
+    fn an_imported_file_that_never_ends_does_not_make_the_next_unreachable() {
+        let imported = "while true {\n    1\n}\n";
+        let text = format!("{}x := 1\n", imported);
+        let (_, ast, errors) = parse_file(&text);
+        let (ast, type_table) = ast.unwrap();
+        let uri = Url::parse("file:///main.lang").unwrap();
+        let rope = Rope::from_str(&text);
+        let config = Config::default();
+        let unreachable = |file_starts: &[usize]| {
+            get_file_diagnostics(
+                &config,
+                &uri,
+                &errors,
+                Some(&ast),
+                &type_table,
+                &rope,
+                file_starts,
+            )
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("unreachable-code".to_string()))
+            })
+            .count()
+        };
+        assert_eq!(unreachable(&[]), 1);
+        assert_eq!(unreachable(&[imported.chars().count()]), 0);
+    }
+}
@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use ropey::Rope;
+
+use crate::database::Analysis;
+
+// Everything we know about one version of an open document. It is built once the analysis
+// finishes and replaces the previous one as a whole, so the text, the AST and the diagnostics
+// a request sees always belong to the same version
+#[derive(Debug)]
+pub struct DocumentSnapshot {
+    pub version: i32,
+    pub rope: Rope,
+    // Shared with the database, the same text analysed again reuses it
+    pub analysis: Arc<Analysis>,
+    pub result_id: String,
+}
@@ -0,0 +1,219 @@
+use std::fmt::Write;
+
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    inferer::Inferer,
+    token::{Span, Spanned, Token},
+    types::Type,
+};
+use ropey::Rope;
+
+use crate::offset_to_position;
+use crate::semantic_tokens::{make_tokens_of_ast, make_tokens_semantic, LEGEND_TYPE};
+
+// Longest piece of source shown next to a type
+const SNIPPET_LEN: usize = 40;
+
+// 1 based line:column, like editors show them
+fn location(span: &Span, rope: &Rope) -> String {
+    let show = |offset: usize| match offset_to_position(offset, rope) {
+        Some(position) => format!("{}:{}", position.line + 1, position.character + 1),
+        None => format!("@{}", offset),
+    };
+    format!("{}-{}", show(span.start), show(span.end))
+}
+
+fn snippet(span: &Span, rope: &Rope) -> String {
+    let text = rope
+        .get_slice(span.clone())
+        .map(|text| text.to_string())
+        .unwrap_or_default();
+    let line = text.lines().next().unwrap_or("");
+    if line.chars().count() > SNIPPET_LEN || text.contains('\n') {
+        format!("{}…", line.chars().take(SNIPPET_LEN).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn concrete(ty: &Option<Type>, type_table: &[Type]) -> Option<Type> {
+    ty.as_ref()
+        .map(|ty| Inferer::get_most_concrete_type(ty, type_table))
+}
+
+pub fn dump_tokens(tokens: &[Spanned<Token>], rope: &Rope) -> String {
+    let mut text = String::new();
+    for (token, span) in tokens {
+        let _ = writeln!(text, "{:<16} {:?}", location(span, rope), token);
+    }
+    text
+}
+
+fn children(node: &Anotated<Ast>) -> Vec<&Anotated<Ast>> {
+    match &node.0 {
+        Ast::Error | Ast::Literal(_) | Ast::Variable(_) | Ast::Coment(_) | Ast::Type(_) => vec![],
+        Ast::Declaration(_, _, ty, _, value) => {
+            ty.iter().chain(value.iter()).map(|b| &**b).collect()
+        }
+        Ast::Call(caller, args) => std::iter::once(&**caller).chain(args.iter()).collect(),
+        Ast::Binary(l, _, r) => vec![&**l, &**r],
+        Ast::While(_, cond, body) => vec![&**cond, &**body],
+        Ast::If(_, cond, if_body, _, else_body) => vec![&**cond, &**if_body, &**else_body],
+        Ast::Tuple(args) | Ast::Block(args) => args.iter().collect(),
+        Ast::Lambda(args, _, body) => args.iter().chain(std::iter::once(&**body)).collect(),
+    }
+}
+
+fn label(node: &Ast) -> String {
+    match node {
+        Ast::Error => "Error".to_string(),
+        Ast::Literal((token, _)) => format!("Literal {:?}", token),
+        Ast::Variable((token, _)) => format!("Variable {:?}", token),
+        Ast::Declaration(..) => "Declaration".to_string(),
+        Ast::Call(..) => "Call".to_string(),
+        Ast::Binary(_, (op, _), _) => format!("Binary {:?}", op),
+        Ast::While(..) => "While".to_string(),
+        Ast::If(..) => "If".to_string(),
+        Ast::Tuple(_) => "Tuple".to_string(),
+        Ast::Block(_) => "Block".to_string(),
+        Ast::Lambda(..) => "Lambda".to_string(),
+        Ast::Coment(_) => "Comment".to_string(),
+        Ast::Type(ty) => format!("Type {}", ty),
+    }
+}
+
+fn dump_pattern(
+    pattern: &Anotated<Pattern>,
+    depth: usize,
+    type_table: &[Type],
+    rope: &Rope,
+    text: &mut String,
+) {
+    let name = match &pattern.0 {
+        Pattern::Var((name, _)) => format!("Pattern {}", name),
+        Pattern::Tuple(_) => "Pattern (..)".to_string(),
+    };
+    let _ = write!(
+        text,
+        "{}{} {}",
+        "  ".repeat(depth),
+        name,
+        location(&pattern.1, rope)
+    );
+    if let Some(ty) = concrete(&pattern.2, type_table) {
+        let _ = write!(text, " : {}", ty);
+    }
+    text.push('\n');
+    if let Pattern::Tuple(args) = &pattern.0 {
+        for arg in args {
+            dump_pattern(arg, depth + 1, type_table, rope, text);
+        }
+    }
+}
+
+fn dump_node(
+    node: &Anotated<Ast>,
+    depth: usize,
+    type_table: &[Type],
+    rope: &Rope,
+    text: &mut String,
+) {
+    let _ = write!(
+        text,
+        "{}{} {}",
+        "  ".repeat(depth),
+        label(&node.0),
+        location(&node.1, rope)
+    );
+    if let Some(ty) = concrete(&node.2, type_table) {
+        let _ = write!(text, " : {}", ty);
+    }
+    text.push('\n');
+    if let Ast::Declaration(pattern, ..) = &node.0 {
+        dump_pattern(pattern, depth + 1, type_table, rope, text);
+    }
+    for child in children(node) {
+        dump_node(child, depth + 1, type_table, rope, text);
+    }
+}
+
+// The tree with the span and the most concrete type of every node
+pub fn dump_ast(ast: &[Anotated<Ast>], type_table: &[Type], rope: &Rope) -> String {
+    let mut text = String::new();
+    for node in ast {
+        dump_node(node, 0, type_table, rope, &mut text);
+    }
+    text
+}
+
+fn collect_types(node: &Anotated<Ast>, type_table: &[Type], rope: &Rope, text: &mut String) {
+    if let Some(ty) = concrete(&node.2, type_table) {
+        let _ = writeln!(
+            text,
+            "{:<16} {:<width$} : {}",
+            location(&node.1, rope),
+            snippet(&node.1, rope),
+            ty,
+            width = SNIPPET_LEN + 1
+        );
+    }
+    for child in children(node) {
+        collect_types(child, type_table, rope, text);
+    }
+}
+
+// Every node that has a type, in source order
+pub fn dump_types(ast: &[Anotated<Ast>], type_table: &[Type], rope: &Rope) -> String {
+    let mut text = String::new();
+    for node in ast {
+        collect_types(node, type_table, rope, &mut text);
+    }
+    text
+}
+
+// Each entry of the type table as the inferer left it and resolved
+pub fn dump_type_table(type_table: &[Type]) -> String {
+    let mut text = String::new();
+    for (i, ty) in type_table.iter().enumerate() {
+        let _ = writeln!(
+            text,
+            "{:>4}  {}  =>  {}",
+            i,
+            ty,
+            Inferer::get_most_concrete_type(ty, type_table)
+        );
+    }
+    text
+}
+
+// The tokens semantic_tokens_full sends, with the deltas decoded back to lines and columns
+pub fn dump_semantic_tokens(ast: &[Anotated<Ast>], type_table: &[Type], rope: &Rope) -> String {
+    let mut tokens = Vec::new();
+    for node in ast {
+        make_tokens_of_ast(node, type_table, &mut tokens);
+    }
+    tokens.sort_by_key(|(_, span)| span.start);
+
+    let mut text = String::new();
+    let (mut line, mut start) = (0, 0);
+    for token in make_tokens_semantic(&tokens, rope) {
+        line += token.delta_line;
+        start = if token.delta_line == 0 {
+            start + token.delta_start
+        } else {
+            token.delta_start
+        };
+        let token_type = LEGEND_TYPE
+            .get(token.token_type as usize)
+            .map_or("?", |token_type| token_type.as_str());
+        let _ = writeln!(
+            text,
+            "{}:{} len {} {}",
+            line + 1,
+            start + 1,
+            token.length,
+            token_type
+        );
+    }
+    text
+}
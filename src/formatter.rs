@@ -0,0 +1,272 @@
+use lang_frontend::{
+    parse_file,
+    token::{Spanned, Token},
+};
+
+use crate::holes::mask_holes;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    // Source text of the token and whether it is an operator
+    Token(String, bool),
+    Comment(String),
+    Space,
+    Newline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Previous {
+    Opening,
+    Comma,
+    BinaryOp,
+    UnaryOp,
+    Other,
+}
+
+fn is_opening(text: &str) -> bool {
+    matches!(text, "(" | "[" | "{")
+}
+
+fn is_closing(text: &str) -> bool {
+    matches!(text, ")" | "]" | "}")
+}
+
+// Splits the whitespace and comments between two tokens. Anything else means the tokens do not
+// cover the source, and formatting could lose code
+fn push_gap(gap: &[char], pieces: &mut Vec<Piece>) -> Option<()> {
+    let mut i = 0;
+    while i < gap.len() {
+        match gap[i] {
+            '\n' => {
+                pieces.push(Piece::Newline);
+                i += 1;
+            }
+            c if c.is_whitespace() => {
+                if pieces.last() != Some(&Piece::Space) {
+                    pieces.push(Piece::Space);
+                }
+                i += 1;
+            }
+            '/' if gap.get(i + 1) == Some(&'/') => {
+                let end = gap[i..]
+                    .iter()
+                    .position(|c| *c == '\n')
+                    .map_or(gap.len(), |len| i + len);
+                let comment = gap[i..end].iter().collect::<String>();
+                pieces.push(Piece::Comment(comment.trim_end().to_string()));
+                i = end;
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+fn split_pieces(text: &str, tokens: &[Spanned<Token>]) -> Option<Vec<Piece>> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut tokens = tokens.iter().collect::<Vec<_>>();
+    tokens.sort_by_key(|(_, span)| span.start);
+
+    let mut pieces = Vec::new();
+    let mut offset = 0;
+    for (token, span) in tokens {
+        if span.start < offset || span.end > chars.len() {
+            return None;
+        }
+        push_gap(&chars[offset..span.start], &mut pieces)?;
+        let source = chars[span.start..span.end].iter().collect::<String>();
+        // Comments can also come as tokens, they keep their text
+        if source.starts_with("//") {
+            pieces.push(Piece::Comment(source.trim_end().to_string()));
+        } else {
+            pieces.push(Piece::Token(source, matches!(token, Token::Op(_))));
+        }
+        offset = span.end;
+    }
+    push_gap(&chars[offset..], &mut pieces)?;
+    Some(pieces)
+}
+
+#[derive(Default)]
+struct Printer {
+    lines: Vec<String>,
+    line: String,
+    depth: usize,
+    // There was whitespace in the source since the previous token
+    space: bool,
+    // None at the start of a line
+    previous: Option<Previous>,
+}
+
+impl Printer {
+    fn indent(&mut self, closing: bool) {
+        let depth = if closing {
+            self.depth.saturating_sub(1)
+        } else {
+            self.depth
+        };
+        self.line = INDENT.repeat(depth);
+    }
+
+    fn token(&mut self, text: &str, is_op: bool) {
+        let kind = if is_opening(text) {
+            Previous::Opening
+        } else if text == "," {
+            Previous::Comma
+        } else if is_op {
+            match self.previous {
+                Some(Previous::Other) => Previous::BinaryOp,
+                _ => Previous::UnaryOp,
+            }
+        } else {
+            Previous::Other
+        };
+
+        match self.previous {
+            None => self.indent(is_closing(text)),
+            Some(previous) => {
+                let separator = match (previous, kind) {
+                    (Previous::Opening | Previous::UnaryOp, _) => false,
+                    _ if is_closing(text) || text == "," => false,
+                    (Previous::Comma | Previous::BinaryOp, _) | (_, Previous::BinaryOp) => true,
+                    _ => self.space,
+                };
+                if separator {
+                    self.line.push(' ');
+                }
+            }
+        }
+        self.line.push_str(text);
+
+        if is_opening(text) {
+            self.depth += 1;
+        } else if is_closing(text) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        self.previous = Some(kind);
+        self.space = false;
+    }
+
+    fn comment(&mut self, text: &str) {
+        match self.previous {
+            None => self.indent(false),
+            Some(_) => self.line.push(' '),
+        }
+        self.line.push_str(text);
+        self.previous = Some(Previous::Other);
+    }
+
+    fn newline(&mut self) {
+        self.lines.push(self.line.trim_end().to_string());
+        self.line.clear();
+        self.previous = None;
+        self.space = false;
+    }
+
+    // At most one blank line in a row, none at the start or the end, and a final newline
+    fn finish(mut self) -> String {
+        if self.previous.is_some() {
+            self.newline();
+        }
+        let mut text = String::new();
+        let mut blank = true;
+        for line in self.lines {
+            if line.is_empty() {
+                if !blank {
+                    text.push('\n');
+                }
+                blank = true;
+            } else {
+                text.push_str(&line);
+                text.push('\n');
+                blank = false;
+            }
+        }
+        while text.ends_with("\n\n") {
+            text.pop();
+        }
+        text
+    }
+}
+
+// Pretty prints the source from its tokens. None if the tokens do not cover the whole text
+pub fn format_tokens(text: &str, tokens: &[Spanned<Token>]) -> Option<String> {
+    let mut printer = Printer::default();
+    for piece in split_pieces(text, tokens)? {
+        match piece {
+            Piece::Token(text, is_op) => printer.token(&text, is_op),
+            Piece::Comment(text) => printer.comment(&text),
+            Piece::Space => printer.space = true,
+            Piece::Newline => printer.newline(),
+        }
+    }
+    Some(printer.finish())
+}
+
+// The formatter of the editor and of `lang-lsp fmt`. None if the file can not be tokenized
+pub fn format(text: &str) -> Option<String> {
+    // Holes are printed from the source, so `?name` stays as it was
+    let (tokens, _, _) = parse_file(&mask_holes(text));
+    format_tokens(text, &tokens?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &[&str] = &[
+        "x:=1+2*3\n",
+        "f := ( a , b ) -> {\nprint( a )\n\n\n\n      b\n}\n",
+        "// comment   \ny :=  \"s  t\"   // trailing\n\n\n",
+        "if x==1 {\n  print(-x)\n} else {\n    while x<3 {x = x+1}\n}\n",
+        "(a,b):=(1,(2,3))\nz := ?hole + _\n",
+        "g := () -> {\n{\n1\n}\n}",
+    ];
+
+    fn tokens(text: &str) -> Vec<Token> {
+        let (tokens, _, _) = parse_file(&mask_holes(text));
+        tokens
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    #[test]
+    fn formatting_twice_changes_nothing() {
+        for sample in SAMPLES {
+            let once = format(sample).unwrap();
+            assert_eq!(
+                format(&once).as_deref(),
+                Some(once.as_str()),
+                "{:?}",
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn formatting_keeps_the_tokens() {
+        for sample in SAMPLES {
+            let formatted = format(sample).unwrap();
+            assert_eq!(tokens(&formatted), tokens(sample), "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn holes_and_comments_are_printed_from_the_source() {
+        let formatted = format("z := ?hole   // keep  me\n").unwrap();
+        assert_eq!(formatted, "z := ?hole // keep  me\n");
+    }
+
+    #[test]
+    fn tokens_that_do_not_cover_the_text_are_rejected() {
+        let text = "x := 1\n";
+        let (tokens, _, _) = parse_file(text);
+        let mut tokens = tokens.unwrap();
+        tokens.pop();
+        assert_eq!(format_tokens(text, &tokens), None);
+    }
+}
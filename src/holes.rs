@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    inferer::Inferer,
+    token::{Span, Token},
+    types::Type,
+};
+use ropey::Rope;
+
+use crate::lints::{Lint, Warning};
+use crate::scope::Scopes;
+use crate::types::fits;
+
+// Fitting bindings listed in the message, an unconstrained hole fits everything
+const MAX_FITS: usize = 10;
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Spans of the holes in the text, `?name` and a lone `_`, skipping strings and comments
+pub fn find_holes(text: &str) -> Vec<Span> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut holes = Vec::new();
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut escaped = false;
+    for (i, c) in chars.iter().enumerate() {
+        if in_comment {
+            in_comment = *c != '\n';
+            continue;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        let after_name = i > 0 && is_name_char(chars[i - 1]);
+        let next = chars.get(i + 1).copied();
+        match c {
+            '"' => in_string = true,
+            '/' if next == Some('/') => in_comment = true,
+            '?' if !after_name && next.is_some_and(|next| next.is_alphabetic() || next == '_') => {
+                let len = chars[i + 1..]
+                    .iter()
+                    .take_while(|c| is_name_char(**c))
+                    .count();
+                holes.push(i..i + 1 + len);
+            }
+            '_' if !after_name && !next.is_some_and(is_name_char) => holes.push(i..i + 1),
+            _ => (),
+        }
+    }
+    holes
+}
+
+// A name of len chars nobody wrote in the text: only underscores, or an underscore and a
+// number padded with underscores. None once every one of that length is taken
+fn free_name(len: usize, taken: &HashSet<String>) -> Option<String> {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    for n in 0usize.. {
+        let mut digits = String::new();
+        let mut rest = n;
+        while rest > 0 {
+            digits.insert(0, DIGITS[rest % DIGITS.len()] as char);
+            rest /= DIGITS.len();
+        }
+        if digits.len() >= len {
+            break;
+        }
+        let name = format!("{}{}", "_".repeat(len - digits.len()), digits);
+        if !taken.contains(&name) {
+            return Some(name);
+        }
+    }
+    None
+}
+
+// The lexer does not know `?name`, so before parsing each hole becomes a name of the same length
+// that is not in the text. Every span still points to the real text, and a hole is never taken
+// for a variable the user called `_name`
+pub fn mask_holes(text: &str) -> String {
+    let mut chars = text.chars().collect::<Vec<_>>();
+    let holes = find_holes(text)
+        .into_iter()
+        .filter(|hole| chars[hole.start] == '?')
+        .collect::<Vec<_>>();
+    if holes.is_empty() {
+        return text.to_string();
+    }
+
+    let mut taken = text
+        .split(|c: char| !is_name_char(c))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect::<HashSet<_>>();
+    // The same hole twice is the same name
+    let mut masks = HashMap::new();
+    for hole in holes {
+        let original = chars[hole.clone()].iter().collect::<String>();
+        let mask = match masks.get(&original) {
+            Some(mask) => mask,
+            None => match free_name(hole.len(), &taken) {
+                Some(mask) => {
+                    taken.insert(mask.clone());
+                    masks.entry(original).or_insert(mask)
+                }
+                None => continue,
+            },
+        };
+        for (i, c) in hole.zip(mask.chars()) {
+            chars[i] = c;
+        }
+    }
+    chars.into_iter().collect()
+}
+
+// `_` or `?name` where an expression goes. The rope has the text before masking
+fn hole_name(node: &Ast, rope: &Rope) -> Option<String> {
+    match node {
+        Ast::Variable((Token::Ident(name), span)) => {
+            if name == "_" {
+                Some(name.clone())
+            } else if rope.get_char(span.start) == Some('?') {
+                rope.get_slice(span.clone()).map(|name| name.to_string())
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn hole_warning(
+    name: &str,
+    span: &Span,
+    ty: &Option<Type>,
+    scopes: &Scopes,
+    type_table: &[Type],
+) -> Warning {
+    let expected = ty
+        .as_ref()
+        .map(|ty| Inferer::get_most_concrete_type(ty, type_table));
+
+    let mut message = match &expected {
+        Some(expected) => format!("Hole `{}` expects `{}`", name, expected),
+        None => format!("Hole `{}` of unknown type", name),
+    };
+
+    let candidates = scopes
+        .visible_at(span.start)
+        .into_iter()
+        .map(|index| &scopes.bindings[index])
+        .filter(|binding| binding.name != "_")
+        .filter_map(|binding| {
+            let ty = Inferer::get_most_concrete_type(binding.ty.as_ref()?, type_table);
+            match &expected {
+                Some(expected) if !fits(&ty, expected) => None,
+                _ => Some(format!("`{}: {}`", binding.name, ty)),
+            }
+        })
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        message.push_str("\nNo binding in scope fits");
+    } else {
+        message.push_str(&format!(
+            "\nBindings that fit: {}",
+            candidates
+                .iter()
+                .take(MAX_FITS)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        if candidates.len() > MAX_FITS {
+            message.push_str(&format!(" and {} more", candidates.len() - MAX_FITS));
+        }
+    }
+
+    Warning {
+        lint: Lint::TypedHole,
+        span: span.clone(),
+        message,
+        related: None,
+    }
+}
+
+fn check_node(
+    (node, span, ty): &Anotated<Ast>,
+    scopes: &Scopes,
+    type_table: &[Type],
+    rope: &Rope,
+    warnings: &mut Vec<Warning>,
+) {
+    if let Some(name) = hole_name(node, rope) {
+        warnings.push(hole_warning(&name, span, ty, scopes, type_table));
+        return;
+    }
+    match node {
+        Ast::Error | Ast::Literal(_) | Ast::Variable(_) | Ast::Coment(_) | Ast::Type(_) => (),
+        Ast::Declaration(_, _, _, _, value) => {
+            if let Some(value) = value {
+                check_node(value, scopes, type_table, rope, warnings);
+            }
+        }
+        Ast::Call(caller, args) => {
+            check_node(caller, scopes, type_table, rope, warnings);
+            for arg in args {
+                check_node(arg, scopes, type_table, rope, warnings);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            check_node(l, scopes, type_table, rope, warnings);
+            check_node(r, scopes, type_table, rope, warnings);
+        }
+        Ast::While(_, cond, body) => {
+            check_node(cond, scopes, type_table, rope, warnings);
+            check_node(body, scopes, type_table, rope, warnings);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            check_node(cond, scopes, type_table, rope, warnings);
+            check_node(if_body, scopes, type_table, rope, warnings);
+            check_node(else_body, scopes, type_table, rope, warnings);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                check_node(arg, scopes, type_table, rope, warnings);
+            }
+        }
+        Ast::Lambda(_, _, body) => check_node(body, scopes, type_table, rope, warnings),
+    }
+}
+
+// Every hole with the type inference expects there and the bindings that could fill it
+pub fn check_holes(
+    ast: &[Anotated<Ast>],
+    scopes: &Scopes,
+    type_table: &[Type],
+    rope: &Rope,
+    warnings: &mut Vec<Warning>,
+) {
+    for node in ast {
+        check_node(node, scopes, type_table, rope, warnings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+    use crate::scope;
+
+    fn hole_texts(text: &str) -> Vec<String> {
+        let chars = text.chars().collect::<Vec<_>>();
+        find_holes(text)
+            .into_iter()
+            .map(|hole| chars[hole].iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn holes_are_found_outside_strings_and_comments() {
+        let text = "a := ?x + _\ns := \"?y _ \\\"?z\\\" _\"\n// ?w _\nb := f(_, ?v)\n";
+        assert_eq!(hole_texts(text), vec!["?x", "_", "_", "?v"]);
+    }
+
+    #[test]
+    fn names_with_question_marks_or_underscores_are_not_holes() {
+        assert!(hole_texts("c := a_b + x?y + _c + d_ + ?1\n").is_empty());
+    }
+
+    #[test]
+    fn masking_keeps_the_length_and_avoids_the_names_of_the_text() {
+        let text = "__ := 1\ny := ?x + ?x + ?ab + ?z\n";
+        let masked = mask_holes(text);
+        assert_eq!(masked, "__ := 1\ny := _1 + _1 + ___ + _2\n");
+        assert_eq!(masked.chars().count(), text.chars().count());
+        assert_eq!(mask_holes("x := \"?a\"\n"), "x := \"?a\"\n");
+    }
+
+    #[test]
+    fn a_hole_is_not_the_binding_with_its_name() {
+        let text = "_x := 1\ny := _x + ?x\n";
+        let (_, ast, _) = parse_file(&mask_holes(text));
+        let (ast, type_table) = ast.unwrap();
+        let scopes = scope::resolve(&ast);
+        // Only the real use of `_x`, the hole refers to nothing
+        assert_eq!(scopes.references.len(), 1);
+        assert_eq!(scopes.references[0].0, 13..15);
+
+        let mut warnings = Vec::new();
+        check_holes(
+            &ast,
+            &scopes,
+            &type_table,
+            &Rope::from_str(text),
+            &mut warnings,
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span, 18..20);
+        assert!(warnings[0].message.starts_with("Hole `?x`"));
+    }
+}
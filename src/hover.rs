@@ -1,39 +1,91 @@
 use lang_frontend::{
     ast::{Anotated, Ast, Pattern},
+    inferer::Inferer,
+    token::{Span, Token},
     types::Type,
 };
+use ropey::Rope;
 
-fn find_match_pattern(pattern: &Anotated<Pattern>, pos: usize) -> Option<Type> {
+use crate::imports::{find_declaration, ImportedModule};
+use crate::scope::{Binding, BindingKind, Scopes};
+
+#[derive(Debug, Clone)]
+pub enum HoverNode {
+    Literal,
+    Name(String),
+    Type,
+    Expression,
+}
+
+#[derive(Debug, Clone)]
+pub struct HoverMatch {
+    pub node: HoverNode,
+    pub ty: Type,
+    pub span: Span,
+}
+
+fn matched(node: HoverNode, ty: &Option<Type>, span: &Span) -> Option<HoverMatch> {
+    ty.clone().map(|ty| HoverMatch {
+        node,
+        ty,
+        span: span.clone(),
+    })
+}
+
+// fallback is the type of the whole binding, used when the pattern itself was not annotated
+fn find_match_pattern(
+    pattern: &Anotated<Pattern>,
+    fallback: &Option<Type>,
+    pos: usize,
+) -> Option<HoverMatch> {
     match &pattern.0 {
-        Pattern::Var((_, name_span)) => {
+        Pattern::Var((name, name_span)) => {
             if name_span.contains(&pos) {
-                return pattern.2.clone();
+                let ty = pattern.2.clone().or_else(|| fallback.clone());
+                return matched(HoverNode::Name(name.clone()), &ty, name_span);
             }
         }
         Pattern::Tuple(args) => {
             for arg in args {
-                if let Some(t) = find_match_pattern(arg, pos) {
+                if let Some(t) = find_match_pattern(arg, &None, pos) {
                     return Some(t);
                 }
             }
             if pattern.1.contains(&pos) {
-                return pattern.2.clone();
+                let ty = pattern.2.clone().or_else(|| fallback.clone());
+                return matched(HoverNode::Expression, &ty, &pattern.1);
             }
         }
     }
     None
 }
 
-pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Option<Type> {
+fn is_comment_at((node, node_span, _): &Anotated<Ast>, pos: usize) -> bool {
+    matches!(node, Ast::Coment(_)) && node_span.contains(&pos)
+}
+
+pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Option<HoverMatch> {
+    // The span of a declaration does not always cover its pattern, so the name is checked first
+    if let Ast::Declaration(pattern, _, ty, _, value) = node {
+        let annotation = ty.as_ref().and_then(|ty| match &ty.0 {
+            Ast::Type(t) => Some(t.clone()),
+            _ => None,
+        });
+        let fallback = annotation.or_else(|| value.as_ref().and_then(|value| value.2.clone()));
+        if let Some(t) = find_match_pattern(pattern, &fallback, pos) {
+            return Some(t);
+        }
+    }
+
     if node_span.contains(&pos) {
         match &node {
-            Ast::Error | Ast::Literal(_) | Ast::Variable(_) => node_ty.clone(),
-            Ast::Declaration(pattern, _, ty, _, value) => {
-                // FIXME this does not work????
-                if let Some(t) = find_match_pattern(pattern, pos) {
-                    return Some(t);
-                }
-
+            Ast::Error => matched(HoverNode::Expression, node_ty, node_span),
+            Ast::Literal(_) => matched(HoverNode::Literal, node_ty, node_span),
+            Ast::Variable((Token::Ident(name), _)) => {
+                matched(HoverNode::Name(name.clone()), node_ty, node_span)
+            }
+            Ast::Variable(_) => matched(HoverNode::Expression, node_ty, node_span),
+            Ast::Declaration(_, _, ty, _, value) => {
                 if let Some(ty) = ty {
                     if let Some(t) = find_match(ty, pos) {
                         return Some(t);
@@ -57,7 +109,7 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                         return Some(t);
                     }
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::Binary(l, _, r) => {
                 if let Some(t) = find_match(l, pos) {
@@ -66,7 +118,7 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                 if let Some(t) = find_match(r, pos) {
                     return Some(t);
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::While(_, cond, body) => {
                 if let Some(t) = find_match(cond, pos) {
@@ -75,7 +127,7 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                 if let Some(t) = find_match(body, pos) {
                     return Some(t);
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::If(_, cond, if_body, _, else_body) => {
                 if let Some(t) = find_match(cond, pos) {
@@ -87,7 +139,7 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                 if let Some(t) = find_match(else_body, pos) {
                     return Some(t);
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::Tuple(args) => {
                 for arg in args {
@@ -95,15 +147,19 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                         return Some(t);
                     }
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::Block(args) => {
                 for arg in args {
+                    // Hovering a comment shows nothing, not the type of the block around it
+                    if is_comment_at(arg, pos) {
+                        return None;
+                    }
                     if let Some(t) = find_match(arg, pos) {
                         return Some(t);
                     }
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
             Ast::Lambda(args, _, ret) => {
                 for arg in args {
@@ -114,14 +170,108 @@ pub fn find_match((node, node_span, node_ty): &Anotated<Ast>, pos: usize) -> Opt
                 if let Some(t) = find_match(ret, pos) {
                     return Some(t);
                 }
-                node_ty.clone()
+                matched(HoverNode::Expression, node_ty, node_span)
             }
-            // TODO this still makes the pop up say Type: ()
             Ast::Coment(_) => None,
-            // TODO
-            Ast::Type(_) => Some(Type::Type),
+            Ast::Type(t) => matched(HoverNode::Type, &Some(t.clone()), node_span),
         }
     } else {
         None
     }
 }
+
+// The // comments in the lines right before a declaration statement. A parameter or a
+// declaration inside an expression shares its line, the comment is not theirs
+fn get_doc_comment(binding: &Binding, rope: &Rope) -> Option<String> {
+    if binding.kind != BindingKind::Variable || !binding.statement {
+        return None;
+    }
+    let mut line = rope.try_char_to_line(binding.decl_span.start).ok()?;
+    let mut lines = Vec::new();
+    while line > 0 {
+        line -= 1;
+        let text = rope.line(line).to_string();
+        match text.trim().strip_prefix("//") {
+            // Suppression comments are not documentation
+            Some(comment) if comment.trim().starts_with("lang-ignore:") => (),
+            Some(comment) => lines.push(comment.trim().to_string()),
+            None => break,
+        }
+    }
+    if lines.is_empty() {
+        None
+    } else {
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+}
+
+// Lists the parts of function and tuple types so annotations show more than the one liner
+fn describe_type_structure(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Fn(args, ret) => {
+            let mut text = String::from("Parameters:\n");
+            for arg in args.iter() {
+                text.push_str(&format!("- `{}`\n", arg));
+            }
+            text.push_str(&format!("\nReturns: `{}`", ret));
+            Some(text)
+        }
+        Type::Tuple(members) => {
+            let mut text = String::from("Members:\n");
+            for (i, member) in members.iter().enumerate() {
+                text.push_str(&format!("- {}: `{}`\n", i, member));
+            }
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+pub fn make_hover_text(
+    hover: &HoverMatch,
+    scopes: &Scopes,
+    type_table: &[Type],
+    rope: &Rope,
+    imports: &[ImportedModule],
+) -> String {
+    let ty = Inferer::get_most_concrete_type(&hover.ty, type_table);
+    let is_function = matches!(ty, Type::Fn(_, _));
+
+    let (kind, name, doc) = match &hover.node {
+        HoverNode::Literal => ("literal", None, None),
+        HoverNode::Type => ("type", None, describe_type_structure(&ty)),
+        HoverNode::Expression => ("expression", None, None),
+        HoverNode::Name(name) => match scopes.binding_at(hover.span.start) {
+            Some(binding) => {
+                let binding = &scopes.bindings[binding];
+                let kind = match binding.kind {
+                    BindingKind::Parameter => "parameter",
+                    BindingKind::Variable if is_function => "function",
+                    BindingKind::Variable => "variable",
+                };
+                (kind, Some(name), get_doc_comment(binding, rope))
+            }
+            // Declared in one of the imported files
+            None => match find_declaration(imports, name) {
+                Some((module, binding)) => (
+                    if is_function { "function" } else { "variable" },
+                    Some(name),
+                    get_doc_comment(binding, &module.rope),
+                ),
+                None if is_function => ("function", Some(name), None),
+                None => ("variable", Some(name), None),
+            },
+        },
+    };
+
+    let mut text = match name {
+        Some(name) => format!("**{}** `{}`\n", kind, name),
+        None => format!("**{}**\n", kind),
+    };
+    text.push_str(&format!("```lang\n{}\n```", ty));
+    if let Some(doc) = doc {
+        text.push_str(&format!("\n---\n{}", doc));
+    }
+    text
+}
@@ -0,0 +1,636 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::PathBuf;
+
+use chumsky::error::{Simple, SimpleReason};
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    parse_file,
+    token::{Span, Spanned, Token},
+    types::Type,
+};
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    Position, Range, Url,
+};
+
+use crate::config::Config;
+use crate::diagnostics::{
+    code_description, get_file_diagnostics, make_diagnostic, ErrorCode, SOURCE,
+};
+use crate::holes::mask_holes;
+use crate::quick_fixes::QuickFix;
+use crate::scope::{self, Binding, Scopes};
+use crate::span_to_range;
+
+// `// import: math.lang` makes the top level declarations of math.lang visible in the file
+const IMPORT_DIRECTIVE: &str = "import:";
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    // Span of the path in the directive
+    pub span: Span,
+}
+
+// A file pulled in by the imports of a document, with the spans of its AST in its own text
+#[derive(Debug)]
+pub struct ImportedModule {
+    pub uri: Url,
+    pub rope: Rope,
+    pub ast: Vec<Anotated<Ast>>,
+    pub scopes: Scopes,
+}
+
+// The last top level declaration with that name in the imported modules
+pub fn find_declaration<'a>(
+    imports: &'a [ImportedModule],
+    name: &str,
+) -> Option<(&'a ImportedModule, &'a Binding)> {
+    imports.iter().rev().find_map(|module| {
+        let binding = module
+            .scopes
+            .bindings
+            .iter()
+            .rev()
+            .find(|binding| binding.depth == 0 && binding.name == name)?;
+        Some((module, binding))
+    })
+}
+
+pub fn find_imports(rope: &Rope) -> Vec<Import> {
+    let mut imports = Vec::new();
+    for (line, text) in rope.lines().enumerate() {
+        let text = text.to_string();
+        let path = match text
+            .trim_start()
+            .strip_prefix("//")
+            .and_then(|comment| comment.trim_start().strip_prefix(IMPORT_DIRECTIVE))
+        {
+            Some(path) => path.trim(),
+            None => continue,
+        };
+        if path.is_empty() {
+            continue;
+        }
+        // The path is a slice of text, so its byte offset gives the column
+        let column = text[..path.as_ptr() as usize - text.as_ptr() as usize]
+            .chars()
+            .count();
+        let start = rope.line_to_char(line) + column;
+        imports.push(Import {
+            path: path.to_string(),
+            span: start..start + path.chars().count(),
+        });
+    }
+    imports
+}
+
+// Relative to the importing file first, then to the workspace folders. The file has to be inside
+// a workspace folder, or next to the importer when there is none, so `..` and absolute paths can
+// not pull in any readable file
+pub fn resolve_import(importer: &Url, path: &str, roots: &[PathBuf]) -> Option<Url> {
+    let importer = importer.to_file_path().ok()?;
+    let dir = importer.parent()?;
+    let allowed = if roots.is_empty() {
+        vec![dir.canonicalize().ok()?]
+    } else {
+        roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .collect()
+    };
+    std::iter::once(dir.join(path))
+        .chain(roots.iter().map(|root| root.join(path)))
+        .filter_map(|candidate| candidate.canonicalize().ok())
+        .find(|candidate| {
+            candidate.is_file() && allowed.iter().any(|root| candidate.starts_with(root))
+        })
+        .and_then(|candidate| Url::from_file_path(candidate).ok())
+}
+
+#[derive(Debug)]
+struct Source {
+    uri: Url,
+    text: String,
+    // Directive of the document that pulled this file in, maybe through other imports
+    via: Import,
+}
+
+// A file that does not parse, it is left out of the linked text
+#[derive(Debug)]
+struct Broken {
+    uri: Url,
+    via: Import,
+    message: String,
+    errors: Vec<DiagnosticRelatedInformation>,
+}
+
+struct Collector<'a> {
+    roots: &'a [PathBuf],
+    read: &'a dyn Fn(&Url) -> Option<String>,
+    visiting: Vec<Url>,
+    sources: Vec<Source>,
+    broken: Vec<Broken>,
+    errors: Vec<(Span, String)>,
+}
+
+// Syntax errors of a file on its own. Type errors are fine, but an unclosed delimiter would
+// swallow the text that comes after it, the document itself
+fn parse_errors(uri: &Url, text: &str) -> Vec<DiagnosticRelatedInformation> {
+    let rope = Rope::from_str(text);
+    let (_, _, errors) = parse_file(&mask_holes(text));
+    errors
+        .iter()
+        .filter(|item| !matches!(item.reason(), SimpleReason::Custom(_)))
+        .filter_map(|item| make_diagnostic(item, uri, &rope))
+        .map(|diagnostic| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), diagnostic.range),
+            message: diagnostic.message,
+        })
+        .collect()
+}
+
+impl Collector<'_> {
+    // Dependencies are added before the files that import them
+    fn collect(&mut self, importer: &Url, import: &Import, via: &Import) {
+        let uri = match resolve_import(importer, &import.path, self.roots) {
+            Some(uri) => uri,
+            None => {
+                let message = if std::ptr::eq(import, via) {
+                    format!("Can not find `{}`", import.path)
+                } else {
+                    format!(
+                        "`{}` imports `{}` which can not be found",
+                        via.path, import.path
+                    )
+                };
+                self.errors.push((via.span.clone(), message));
+                return;
+            }
+        };
+        if self.visiting.contains(&uri) {
+            self.errors
+                .push((via.span.clone(), format!("`{}` imports itself", via.path)));
+            return;
+        }
+        if self.sources.iter().any(|source| source.uri == uri)
+            || self.broken.iter().any(|broken| broken.uri == uri)
+        {
+            return;
+        }
+        let text = match (self.read)(&uri) {
+            Some(text) => text,
+            None => {
+                self.errors
+                    .push((via.span.clone(), format!("Can not read `{}`", import.path)));
+                return;
+            }
+        };
+
+        let errors = parse_errors(&uri, &text);
+        if !errors.is_empty() {
+            let message = if std::ptr::eq(import, via) {
+                format!("`{}` has syntax errors", import.path)
+            } else {
+                format!(
+                    "`{}` imports `{}` which has syntax errors",
+                    via.path, import.path
+                )
+            };
+            self.broken.push(Broken {
+                uri,
+                via: via.clone(),
+                message,
+                errors,
+            });
+            return;
+        }
+
+        self.visiting.push(uri.clone());
+        for nested in find_imports(&Rope::from_str(&text)) {
+            self.collect(&uri, &nested, via);
+        }
+        self.visiting.pop();
+
+        self.sources.push(Source {
+            uri,
+            text,
+            via: via.clone(),
+        });
+    }
+}
+
+// The frontend only knows about one file, so the imported files are put before the document
+// and everything it returns is moved back to the file it came from
+#[derive(Debug)]
+pub struct Linked {
+    pub text: String,
+    pub rope: Rope,
+    document: Rope,
+    sources: Vec<Source>,
+    broken: Vec<Broken>,
+    // Char where each source starts, each one begins in a new line
+    offsets: Vec<usize>,
+    prefix_len: usize,
+    prefix_lines: usize,
+    errors: Vec<(Span, String)>,
+}
+
+pub fn link(
+    uri: &Url,
+    text: &str,
+    roots: &[PathBuf],
+    read: &dyn Fn(&Url) -> Option<String>,
+) -> Linked {
+    let document = Rope::from_str(text);
+    let mut collector = Collector {
+        roots,
+        read,
+        visiting: vec![uri.clone()],
+        sources: Vec::new(),
+        broken: Vec::new(),
+        errors: Vec::new(),
+    };
+    for import in find_imports(&document) {
+        collector.collect(uri, &import, &import);
+    }
+
+    let mut linked = String::new();
+    let mut offsets = Vec::new();
+    let mut prefix_len = 0;
+    for source in &collector.sources {
+        offsets.push(prefix_len);
+        linked.push_str(&source.text);
+        linked.push('\n');
+        prefix_len += source.text.chars().count() + 1;
+    }
+    linked.push_str(text);
+
+    let rope = Rope::from_str(&linked);
+    let prefix_lines = rope.char_to_line(prefix_len);
+    Linked {
+        text: linked,
+        rope,
+        document,
+        sources: collector.sources,
+        broken: collector.broken,
+        offsets,
+        prefix_len,
+        prefix_lines,
+        errors: collector.errors,
+    }
+}
+
+fn shift_span(span: &mut Span, offset: usize) {
+    *span = span.start.saturating_sub(offset)..span.end.saturating_sub(offset);
+}
+
+fn shift_pattern(pattern: &mut Anotated<Pattern>, offset: usize) {
+    shift_span(&mut pattern.1, offset);
+    match &mut pattern.0 {
+        Pattern::Var((_, span)) => shift_span(span, offset),
+        Pattern::Tuple(args) => {
+            for arg in args {
+                shift_pattern(arg, offset);
+            }
+        }
+    }
+}
+
+fn shift_spans(node: &mut Anotated<Ast>, offset: usize) {
+    shift_span(&mut node.1, offset);
+    match &mut node.0 {
+        Ast::Error | Ast::Type(_) => (),
+        Ast::Literal((_, span)) | Ast::Variable((_, span)) | Ast::Coment((_, span)) => {
+            shift_span(span, offset)
+        }
+        Ast::Declaration(pattern, (_, span), ty, _, value) => {
+            shift_pattern(pattern, offset);
+            shift_span(span, offset);
+            if let Some(ty) = ty {
+                shift_spans(ty, offset);
+            }
+            if let Some(value) = value {
+                shift_spans(value, offset);
+            }
+        }
+        Ast::Call(caller, args) => {
+            shift_spans(caller, offset);
+            for arg in args {
+                shift_spans(arg, offset);
+            }
+        }
+        Ast::Binary(l, (_, span), r) => {
+            shift_spans(l, offset);
+            shift_span(span, offset);
+            shift_spans(r, offset);
+        }
+        Ast::While((_, span), cond, body) => {
+            shift_span(span, offset);
+            shift_spans(cond, offset);
+            shift_spans(body, offset);
+        }
+        Ast::If((_, span), cond, if_body, else_tk, else_body) => {
+            shift_span(span, offset);
+            shift_spans(cond, offset);
+            shift_spans(if_body, offset);
+            if let Some((_, span)) = else_tk {
+                shift_span(span, offset);
+            }
+            shift_spans(else_body, offset);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                shift_spans(arg, offset);
+            }
+        }
+        Ast::Lambda(args, (_, span), body) => {
+            for arg in args {
+                shift_spans(arg, offset);
+            }
+            shift_span(span, offset);
+            shift_spans(body, offset);
+        }
+    }
+}
+
+fn shift_range(range: &mut Range, lines: u32) {
+    range.start.line = range.start.line.saturating_sub(lines);
+    range.end.line = range.end.line.saturating_sub(lines);
+}
+
+impl Linked {
+    pub fn has_imports(&self) -> bool {
+        !self.sources.is_empty()
+    }
+
+    // Also the files left out, fixing them changes the document
+    pub fn imported_uris(&self) -> Vec<Url> {
+        self.sources
+            .iter()
+            .map(|source| source.uri.clone())
+            .chain(self.broken.iter().map(|broken| broken.uri.clone()))
+            .collect()
+    }
+
+    // Index of the source a char of the linked text belongs to, None for the document
+    fn source_at(&self, offset: usize) -> Option<usize> {
+        if offset >= self.prefix_len {
+            return None;
+        }
+        self.offsets.iter().rposition(|start| *start <= offset)
+    }
+
+    fn source_at_line(&self, line: u32) -> Option<usize> {
+        let line = line as usize;
+        if line >= self.prefix_lines {
+            return None;
+        }
+        self.offsets
+            .iter()
+            .rposition(|start| self.rope.char_to_line(*start) <= line)
+    }
+
+    // The nodes of the document with its own spans, and the modules it imports
+    pub fn split_ast(&self, ast: Vec<Anotated<Ast>>) -> (Vec<Anotated<Ast>>, Vec<ImportedModule>) {
+        let mut document = Vec::new();
+        let mut modules = self.sources.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        for mut node in ast {
+            match self.source_at(node.1.start) {
+                Some(source) => {
+                    shift_spans(&mut node, self.offsets[source]);
+                    modules[source].push(node);
+                }
+                None => {
+                    shift_spans(&mut node, self.prefix_len);
+                    document.push(node);
+                }
+            }
+        }
+
+        let modules = self
+            .sources
+            .iter()
+            .zip(modules)
+            .map(|(source, ast)| ImportedModule {
+                uri: source.uri.clone(),
+                rope: Rope::from_str(&source.text),
+                scopes: scope::resolve(&ast),
+                ast,
+            })
+            .collect();
+        (document, modules)
+    }
+
+    pub fn split_tokens(&self, tokens: Vec<Spanned<Token>>) -> Vec<Spanned<Token>> {
+        tokens
+            .into_iter()
+            .filter(|(_, span)| span.start >= self.prefix_len)
+            .map(|(token, mut span)| {
+                shift_span(&mut span, self.prefix_len);
+                (token, span)
+            })
+            .collect()
+    }
+
+    // Diagnostics of the document. Errors inside the imported files are reported on the
+    // directive that imports them, with the real locations as related information
+    pub fn diagnostics<T: std::fmt::Display + Hash + Eq>(
+        &self,
+        config: &Config,
+        uri: &Url,
+        errors: &[Simple<T>],
+        ast: Option<&[Anotated<Ast>]>,
+        type_table: &[Type],
+    ) -> Vec<Diagnostic> {
+        let mut file_starts = self.offsets[1.min(self.offsets.len())..].to_vec();
+        file_starts.push(self.prefix_len);
+        let all = get_file_diagnostics(
+            config,
+            uri,
+            errors,
+            ast,
+            type_table,
+            &self.rope,
+            &file_starts,
+        );
+        if !self.has_imports() && self.errors.is_empty() && self.broken.is_empty() {
+            return all;
+        }
+        let invalid_program = NumberOrString::String(ErrorCode::InvalidProgram.code().to_string());
+
+        let lines = self.prefix_lines as u32;
+        let mut diagnostics = Vec::new();
+        let mut imported_errors: HashMap<String, (Span, Vec<DiagnosticRelatedInformation>)> =
+            HashMap::new();
+        for mut diagnostic in all {
+            match self.source_at_line(diagnostic.range.start.line) {
+                Some(index) if diagnostic.severity == Some(DiagnosticSeverity::ERROR) => {
+                    let source = &self.sources[index];
+                    let start_line = self.rope.char_to_line(self.offsets[index]) as u32;
+                    shift_range(&mut diagnostic.range, start_line);
+                    imported_errors
+                        .entry(source.via.path.clone())
+                        .or_insert_with(|| (source.via.span.clone(), Vec::new()))
+                        .1
+                        .push(DiagnosticRelatedInformation {
+                            location: Location::new(source.uri.clone(), diagnostic.range),
+                            message: diagnostic.message,
+                        });
+                }
+                // Warnings of the imported files belong to them
+                Some(_) => (),
+                // Without the files left out the names they declare are missing, the type errors
+                // of the document are most likely caused by that
+                None if !self.broken.is_empty()
+                    && diagnostic.code.as_ref() == Some(&invalid_program) => {}
+                None => {
+                    shift_range(&mut diagnostic.range, lines);
+                    if let Some(related) = &mut diagnostic.related_information {
+                        related.retain(|info| info.location.range.start.line >= lines);
+                        for info in related.iter_mut() {
+                            shift_range(&mut info.location.range, lines);
+                        }
+                    }
+                    let fixes = diagnostic
+                        .data
+                        .take()
+                        .and_then(|data| serde_json::from_value::<Vec<QuickFix>>(data).ok());
+                    if let Some(mut fixes) = fixes {
+                        for fix in fixes.iter_mut() {
+                            shift_range(&mut fix.range, lines);
+                        }
+                        diagnostic.data = serde_json::to_value(fixes).ok();
+                    }
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+
+        for (path, (span, related)) in imported_errors {
+            diagnostics.push(self.import_diagnostic(
+                ErrorCode::ImportHasErrors,
+                &span,
+                format!("`{}` has errors", path),
+                Some(related),
+            ));
+        }
+        for broken in &self.broken {
+            diagnostics.push(self.import_diagnostic(
+                ErrorCode::ImportHasErrors,
+                &broken.via.span,
+                broken.message.clone(),
+                Some(broken.errors.clone()),
+            ));
+        }
+        for (span, message) in &self.errors {
+            diagnostics.push(self.import_diagnostic(
+                ErrorCode::UnresolvedImport,
+                span,
+                message.clone(),
+                None,
+            ));
+        }
+        diagnostics
+    }
+
+    fn import_diagnostic(
+        &self,
+        code: ErrorCode,
+        span: &Span,
+        message: String,
+        related_information: Option<Vec<DiagnosticRelatedInformation>>,
+    ) -> Diagnostic {
+        let range = span_to_range(span, &self.document)
+            .unwrap_or_else(|| Range::new(Position::new(0, 0), Position::new(0, 0)));
+        Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            code: Some(NumberOrString::String(code.code().to_string())),
+            code_description: code_description(code.code()),
+            source: Some(SOURCE.to_string()),
+            message,
+            related_information,
+            ..Diagnostic::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    // A fresh directory per test, the tests run in parallel
+    fn workspace(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lang-lsp-imports-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("project/lib")).unwrap();
+        fs::write(dir.join("project/lib/math.lang"), "one := 1\n").unwrap();
+        fs::write(dir.join("secret.lang"), "key := 1\n").unwrap();
+        dir
+    }
+
+    fn importer(dir: &Path) -> Url {
+        Url::from_file_path(dir.join("project/main.lang")).unwrap()
+    }
+
+    #[test]
+    fn resolves_inside_the_workspace() {
+        let dir = workspace("inside");
+        let roots = [dir.join("project")];
+        let uri = resolve_import(&importer(&dir), "lib/math.lang", &roots);
+        let expected = dir.join("project/lib/math.lang").canonicalize().unwrap();
+        assert_eq!(uri, Url::from_file_path(expected).ok());
+        assert!(resolve_import(&importer(&dir), "lib/../lib/math.lang", &roots).is_some());
+    }
+
+    #[test]
+    fn rejects_files_outside_the_workspace() {
+        let dir = workspace("outside");
+        let roots = [dir.join("project")];
+        let absolute = dir.join("secret.lang");
+        assert_eq!(
+            resolve_import(&importer(&dir), "../secret.lang", &roots),
+            None
+        );
+        assert_eq!(
+            resolve_import(&importer(&dir), absolute.to_str().unwrap(), &roots),
+            None
+        );
+        // Without workspace folders the directory of the importer is the limit
+        assert_eq!(resolve_import(&importer(&dir), "../secret.lang", &[]), None);
+        assert!(resolve_import(&importer(&dir), "lib/math.lang", &[]).is_some());
+    }
+
+    #[test]
+    fn outside_imports_are_unresolved() {
+        let dir = workspace("unresolved");
+        let roots = [dir.join("project")];
+        let read = |uri: &Url| fs::read_to_string(uri.to_file_path().ok()?).ok();
+        let linked = link(
+            &importer(&dir),
+            "// import: ../secret.lang\nkey\n",
+            &roots,
+            &read,
+        );
+        assert!(linked.sources.is_empty());
+        let (_, ast, errors) = parse_file(&linked.text);
+        let diagnostics = linked.diagnostics(
+            &Config::default(),
+            &importer(&dir),
+            &errors,
+            ast.as_ref().map(|(ast, _)| ast.as_slice()),
+            &[],
+        );
+        let unresolved = NumberOrString::String(ErrorCode::UnresolvedImport.code().to_string());
+        assert!(diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.code.as_ref() == Some(&unresolved)));
+    }
+}
@@ -5,6 +5,8 @@ use lang_frontend::{
 };
 use std::collections::HashMap;
 
+use crate::const_eval::{fold, Constant};
+
 pub fn get_inlay_hints(node: &Anotated<Ast>, hints: &mut HashMap<Span, Type>) {
     match &node.0 {
         Ast::Declaration(_, (def_tk, span), _, _, Some(value)) => {
@@ -51,3 +53,55 @@ pub fn get_inlay_hints(node: &Anotated<Ast>, hints: &mut HashMap<Span, Type>) {
         _ => (),
     }
 }
+
+// Declarations whose value is a constant expression, with the span of the value and what it
+// folds to. A value that is already a literal says the same thing, it gets no hint
+pub fn get_value_hints(node: &Anotated<Ast>, hints: &mut Vec<(Span, Constant)>) {
+    match &node.0 {
+        Ast::Declaration(_, _, _, _, Some(value)) => {
+            if !matches!(value.0, Ast::Literal(_)) {
+                if let Some(constant) = fold(value) {
+                    hints.push((value.1.clone(), constant));
+                    return;
+                }
+            }
+            get_value_hints(value, hints);
+        }
+        Ast::Call(caller, args) => {
+            get_value_hints(caller, hints);
+            for node in args {
+                get_value_hints(node, hints);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            get_value_hints(l, hints);
+            get_value_hints(r, hints);
+        }
+        Ast::While(_, cond, body) => {
+            get_value_hints(cond, hints);
+            get_value_hints(body, hints);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            get_value_hints(cond, hints);
+            get_value_hints(if_body, hints);
+            get_value_hints(else_body, hints);
+        }
+        Ast::Tuple(args) => {
+            for node in args {
+                get_value_hints(node, hints);
+            }
+        }
+        Ast::Block(expresions) => {
+            for node in expresions {
+                get_value_hints(node, hints);
+            }
+        }
+        Ast::Lambda(args, _, ret) => {
+            for node in args {
+                get_value_hints(node, hints);
+            }
+            get_value_hints(ret, hints);
+        }
+        _ => (),
+    }
+}
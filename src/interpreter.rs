@@ -0,0 +1,672 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    token::{Span, Token},
+    types::Type,
+};
+
+use crate::const_eval::{fold_binary, Constant};
+
+// A runaway loop must not take the server with it
+const MAX_STEPS: usize = 1_000_000;
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Print,
+}
+
+#[derive(Debug)]
+pub struct Closure<'a> {
+    pub args: &'a [Anotated<Ast>],
+    pub body: &'a Anotated<Ast>,
+    pub env: Env<'a>,
+    // Module the lambda was written in
+    pub module: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    // The empty tuple is the unit value
+    Tuple(Vec<Value<'a>>),
+    Closure(Rc<Closure<'a>>),
+    Builtin(Builtin),
+}
+
+impl<'a> Value<'a> {
+    fn unit() -> Self {
+        Value::Tuple(Vec::new())
+    }
+
+    fn constant(&self) -> Option<Constant> {
+        match self {
+            Value::Bool(b) => Some(Constant::Bool(*b)),
+            Value::Number(n) => Some(Constant::Number(*n)),
+            Value::Text(s) => Some(Constant::Text(s.clone())),
+            _ => None,
+        }
+    }
+
+    fn from_constant(constant: Constant) -> Self {
+        match constant {
+            Constant::Bool(b) => Value::Bool(b),
+            Constant::Number(n) => Value::Number(n),
+            Constant::Text(s) => Value::Text(s),
+        }
+    }
+
+    // What print shows, text without quotes
+    pub fn to_output(&self) -> String {
+        match self {
+            Value::Text(s) => s.clone(),
+            value => value.to_string(),
+        }
+    }
+
+    fn equals(&self, other: &Value) -> Option<bool> {
+        match (self, other) {
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                if a.len() != b.len() {
+                    return Some(false);
+                }
+                for (a, b) in a.iter().zip(b.iter()) {
+                    if !a.equals(b)? {
+                        return Some(false);
+                    }
+                }
+                Some(true)
+            }
+            (a, b) => Some(a.constant()? == b.constant()?),
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(s) => write!(f, "{:?}", s),
+            Value::Tuple(members) => {
+                write!(f, "(")?;
+                for (i, member) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", member)?;
+                }
+                write!(f, ")")
+            }
+            Value::Closure(_) => write!(f, "<lambda>"),
+            Value::Builtin(Builtin::Print) => write!(f, "<builtin print>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Variable<'a> {
+    pub name: String,
+    pub value: Value<'a>,
+    // The type inference gave to the name, resolved with the type table of the program
+    pub ty: Option<&'a Type>,
+}
+
+#[derive(Debug)]
+pub struct Scope<'a> {
+    pub vars: RefCell<Vec<Variable<'a>>>,
+    pub parent: Option<Env<'a>>,
+}
+
+pub type Env<'a> = Rc<Scope<'a>>;
+
+pub fn child<'a>(parent: &Env<'a>) -> Env<'a> {
+    Rc::new(Scope {
+        vars: RefCell::new(Vec::new()),
+        parent: Some(parent.clone()),
+    })
+}
+
+fn define<'a>(env: &Env<'a>, name: &str, value: Value<'a>, ty: Option<&'a Type>) {
+    env.vars.borrow_mut().push(Variable {
+        name: name.to_string(),
+        value,
+        ty,
+    });
+}
+
+pub fn lookup<'a>(env: &Env<'a>, name: &str) -> Option<Value<'a>> {
+    let found = env
+        .vars
+        .borrow()
+        .iter()
+        .rev()
+        .find(|var| var.name == name)
+        .map(|var| var.value.clone());
+    match found {
+        Some(value) => Some(value),
+        None => lookup(env.parent.as_ref()?, name),
+    }
+}
+
+// Changes the innermost binding with that name, false if there is none
+fn assign<'a>(env: &Env<'a>, name: &str, value: Value<'a>) -> bool {
+    let mut vars = env.vars.borrow_mut();
+    if let Some(var) = vars.iter_mut().rev().find(|var| var.name == name) {
+        var.value = value;
+        return true;
+    }
+    drop(vars);
+    match &env.parent {
+        Some(parent) => assign(parent, name, value),
+        None => false,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, RuntimeError> {
+    Err(RuntimeError {
+        message: message.into(),
+    })
+}
+
+fn bind_pattern<'a>(
+    env: &Env<'a>,
+    pattern: &'a Anotated<Pattern>,
+    value: Value<'a>,
+) -> Result<(), RuntimeError> {
+    match (&pattern.0, value) {
+        (Pattern::Var((name, _)), value) => define(env, name, value, pattern.2.as_ref()),
+        (Pattern::Tuple(args), Value::Tuple(members)) if args.len() == members.len() => {
+            for (arg, member) in args.iter().zip(members) {
+                bind_pattern(env, arg, member)?;
+            }
+        }
+        (Pattern::Tuple(args), value) => {
+            return error(format!(
+                "can not destructure {} into {} names",
+                value,
+                args.len()
+            ))
+        }
+    }
+    Ok(())
+}
+
+// A call in progress, the first one is the module being loaded
+#[derive(Debug)]
+pub struct Frame<'a> {
+    pub name: String,
+    pub module: usize,
+    // The scope of the statement that runs now, a block inside the call has its own
+    pub env: Env<'a>,
+    // The statement that runs now
+    pub span: Span,
+}
+
+// Lets a debugger stop the program. The interpreter calls it before every statement and
+// waits until it returns, an error ends the program
+pub trait Debugger<'a> {
+    fn statement(
+        &mut self,
+        node: &'a Anotated<Ast>,
+        frames: &[Frame<'a>],
+    ) -> Result<(), RuntimeError>;
+
+    fn print(&mut self, _line: &str) {}
+}
+
+pub struct Interpreter<'a> {
+    pub globals: Env<'a>,
+    // Everything print wrote, in order
+    pub output: Vec<String>,
+    pub frames: Vec<Frame<'a>>,
+    pub debugger: Option<Box<dyn Debugger<'a> + 'a>>,
+    steps: usize,
+}
+
+impl<'a> Default for Interpreter<'a> {
+    fn default() -> Self {
+        let globals = Rc::new(Scope {
+            vars: RefCell::new(Vec::new()),
+            parent: None,
+        });
+        define(&globals, "print", Value::Builtin(Builtin::Print), None);
+        Interpreter {
+            frames: vec![Frame {
+                name: "<module>".to_string(),
+                module: 0,
+                env: globals.clone(),
+                span: 0..0,
+            }],
+            globals,
+            output: Vec::new(),
+            debugger: None,
+            steps: 0,
+        }
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    // Defines the top level declarations of a module, other top level expressions are not run.
+    // Modules are numbered in the order they are loaded
+    pub fn load(&mut self, module: usize, ast: &'a [Anotated<Ast>]) -> Result<(), RuntimeError> {
+        self.frames[0].module = module;
+        let globals = self.globals.clone();
+        for node in ast {
+            if let Ast::Declaration(..) = node.0 {
+                self.statement(node, &globals)?;
+                self.eval(node, &globals)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, node: &'a Anotated<Ast>, env: &Env<'a>) -> Result<(), RuntimeError> {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.env = env.clone();
+            frame.span = node.1.clone();
+        }
+        match self.debugger.as_mut() {
+            Some(debugger) => debugger.statement(node, &self.frames),
+            None => Ok(()),
+        }
+    }
+
+    pub fn eval(
+        &mut self,
+        node: &'a Anotated<Ast>,
+        env: &Env<'a>,
+    ) -> Result<Value<'a>, RuntimeError> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return error(format!("evaluation stopped after {} steps", MAX_STEPS));
+        }
+
+        match &node.0 {
+            Ast::Error => error("the program has errors"),
+            Ast::Coment(_) | Ast::Type(_) => Ok(Value::unit()),
+            Ast::Literal((Token::Bool(b), _)) => Ok(Value::Bool(*b)),
+            Ast::Literal((Token::Number(n), _)) => match n.parse() {
+                Ok(n) => Ok(Value::Number(n)),
+                Err(_) => error(format!("invalid number {}", n)),
+            },
+            Ast::Literal((Token::Text(s), _)) => Ok(Value::Text(s.clone())),
+            Ast::Literal((token, _)) => error(format!("unsupported literal {:?}", token)),
+            Ast::Variable((Token::Ident(name), _)) => match lookup(env, name) {
+                Some(value) => Ok(value),
+                None => error(format!("`{}` is not defined", name)),
+            },
+            Ast::Variable((token, _)) => error(format!("unsupported name {:?}", token)),
+            Ast::Declaration(pattern, (def_tk, _), ty, _, value) => {
+                let value = match value {
+                    Some(value) => value,
+                    // Only a type, there is nothing to run
+                    None => return Ok(Value::unit()),
+                };
+                // Lambdas can call themselves, so the name exists before the closure
+                if let (Pattern::Var((name, _)), Ast::Lambda(..)) = (&pattern.0, &value.0) {
+                    define(env, name, Value::unit(), pattern.2.as_ref());
+                    let value = self.eval(value, env)?;
+                    assign(env, name, value);
+                    return Ok(Value::unit());
+                }
+                let value = self.eval(value, env)?;
+                // `name = value` changes an existing binding, anything else declares a new one
+                if let Pattern::Var((name, _)) = &pattern.0 {
+                    let is_assignment = ty.is_none() && def_tk == &Token::Op("=".to_string());
+                    if is_assignment && assign(env, name, value.clone()) {
+                        return Ok(Value::unit());
+                    }
+                }
+                bind_pattern(env, pattern, value)?;
+                Ok(Value::unit())
+            }
+            Ast::Call(caller, args) => {
+                let function = self.eval(caller, env)?;
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval(arg, env)?);
+                }
+                let name = match &caller.0 {
+                    Ast::Variable((Token::Ident(name), _)) => name.as_str(),
+                    _ => "<lambda>",
+                };
+                self.call(function, values, name)
+            }
+            Ast::Binary(l, (op, _), r) => {
+                let op = match op {
+                    Token::Op(op) => op.as_str(),
+                    op => return error(format!("unsupported operator {:?}", op)),
+                };
+                let l = self.eval(l, env)?;
+                // and/or do not evaluate the right side when the left one decides
+                match (op, &l) {
+                    ("and", Value::Bool(false)) => return Ok(Value::Bool(false)),
+                    ("or", Value::Bool(true)) => return Ok(Value::Bool(true)),
+                    _ => (),
+                }
+                let r = self.eval(r, env)?;
+                self.binary(l, op, r)
+            }
+            Ast::While(_, cond, body) => loop {
+                match self.eval(cond, env)? {
+                    Value::Bool(true) => {
+                        self.eval(body, env)?;
+                    }
+                    Value::Bool(false) => return Ok(Value::unit()),
+                    value => return error(format!("expected a bool, found {}", value)),
+                }
+            },
+            Ast::If(_, cond, if_body, _, else_body) => match self.eval(cond, env)? {
+                Value::Bool(true) => self.eval(if_body, env),
+                Value::Bool(false) => self.eval(else_body, env),
+                value => error(format!("expected a bool, found {}", value)),
+            },
+            Ast::Tuple(args) => {
+                let mut members = Vec::new();
+                for arg in args {
+                    members.push(self.eval(arg, env)?);
+                }
+                Ok(Value::Tuple(members))
+            }
+            Ast::Block(expresions) => {
+                let env = child(env);
+                let mut last = Value::unit();
+                for node in expresions {
+                    // A comment is not a statement, the debugger does not stop there
+                    if matches!(node.0, Ast::Coment(_)) {
+                        continue;
+                    }
+                    self.statement(node, &env)?;
+                    last = self.eval(node, &env)?;
+                }
+                Ok(last)
+            }
+            Ast::Lambda(args, _, body) => Ok(Value::Closure(Rc::new(Closure {
+                args,
+                body,
+                env: env.clone(),
+                module: self.frames.last().map_or(0, |frame| frame.module),
+            }))),
+        }
+    }
+
+    fn binary(&mut self, l: Value<'a>, op: &str, r: Value<'a>) -> Result<Value<'a>, RuntimeError> {
+        if let (Some(a), Some(b)) = (l.constant(), r.constant()) {
+            if let Some(result) = fold_binary(a, op, b) {
+                return Ok(Value::from_constant(result));
+            }
+        } else if let ("==" | "!=", Some(equal)) = (op, l.equals(&r)) {
+            return Ok(Value::Bool(equal == (op == "==")));
+        }
+        error(format!("can not apply `{}` to {} and {}", op, l, r))
+    }
+
+    // name is what the stack trace shows for the call
+    pub fn call(
+        &mut self,
+        function: Value<'a>,
+        values: Vec<Value<'a>>,
+        name: &str,
+    ) -> Result<Value<'a>, RuntimeError> {
+        let closure = match function {
+            Value::Closure(closure) => closure,
+            Value::Builtin(Builtin::Print) => {
+                let line = values
+                    .iter()
+                    .map(|value| value.to_output())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if let Some(debugger) = self.debugger.as_mut() {
+                    debugger.print(&line);
+                }
+                self.output.push(line);
+                return Ok(Value::unit());
+            }
+            value => return error(format!("{} is not a function", value)),
+        };
+        if closure.args.len() != values.len() {
+            return error(format!(
+                "expected {} arguments, found {}",
+                closure.args.len(),
+                values.len()
+            ));
+        }
+        if self.frames.len() > MAX_DEPTH {
+            return error(format!("more than {} nested calls", MAX_DEPTH));
+        }
+
+        let env = child(&closure.env);
+        for (arg, value) in closure.args.iter().zip(values) {
+            match &arg.0 {
+                Ast::Variable((Token::Ident(name), _)) => define(&env, name, value, arg.2.as_ref()),
+                Ast::Declaration(pattern, ..) => bind_pattern(&env, pattern, value)?,
+                _ => return error("unsupported parameter"),
+            }
+        }
+        self.frames.push(Frame {
+            name: name.to_string(),
+            module: closure.module,
+            env: env.clone(),
+            span: closure.body.1.clone(),
+        });
+        // A body that is not a block is a statement of its own
+        let mut result = Ok(());
+        if !matches!(closure.body.0, Ast::Block(_)) {
+            result = self.statement(closure.body, &env);
+        }
+        let result = result.and_then(|_| self.eval(closure.body, &env));
+        self.frames.pop();
+        result
+    }
+}
+
+fn declaration_lambda(node: &Anotated<Ast>) -> Option<&[Anotated<Ast>]> {
+    match &node.0 {
+        Ast::Declaration(_, _, _, _, Some(value)) => match &value.0 {
+            Ast::Lambda(args, ..) => Some(args),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runnable {
+    // A function without parameters, it can be called as it is
+    Run,
+    // Any other value
+    Evaluate,
+}
+
+// The top level declarations that get a code lens
+pub fn find_runnables(ast: &[Anotated<Ast>]) -> Vec<(Span, Runnable)> {
+    ast.iter()
+        .filter_map(|node| match declaration_lambda(node) {
+            Some([]) => Some((node.1.clone(), Runnable::Run)),
+            Some(_) => None,
+            None if matches!(node.0, Ast::Declaration(_, _, _, _, Some(_))) => {
+                Some((node.1.clone(), Runnable::Evaluate))
+            }
+            None => None,
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Evaluation {
+    pub output: Vec<String>,
+    pub result: Result<String, RuntimeError>,
+}
+
+fn pattern_value<'a>(pattern: &Anotated<Pattern>, env: &Env<'a>) -> Option<Value<'a>> {
+    match &pattern.0 {
+        Pattern::Var((name, _)) => lookup(env, name),
+        Pattern::Tuple(args) => args
+            .iter()
+            .map(|arg| pattern_value(arg, env))
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Tuple),
+    }
+}
+
+// Runs the top level declaration that starts at offset: a function without parameters is
+// called, any other declaration is evaluated after the ones before it. The imported modules
+// are loaded first, as the frontend sees them
+pub fn run_declaration(
+    imports: &[&[Anotated<Ast>]],
+    ast: &[Anotated<Ast>],
+    offset: usize,
+) -> Option<Evaluation> {
+    let index = ast.iter().position(|node| node.1.start == offset)?;
+    let node = &ast[index];
+    let pattern = match &node.0 {
+        Ast::Declaration(pattern, ..) => pattern,
+        _ => return None,
+    };
+
+    let mut interpreter = Interpreter::default();
+    let mut run = || -> Result<String, RuntimeError> {
+        for (module, ast) in imports.iter().copied().enumerate() {
+            interpreter.load(module, ast)?;
+        }
+        match declaration_lambda(node) {
+            Some([]) => {
+                interpreter.load(imports.len(), ast)?;
+                let function =
+                    pattern_value(pattern, &interpreter.globals).unwrap_or_else(Value::unit);
+                let name = match &pattern.0 {
+                    Pattern::Var((name, _)) => name.as_str(),
+                    _ => "<lambda>",
+                };
+                Ok(interpreter.call(function, vec![], name)?.to_string())
+            }
+            _ => {
+                interpreter.load(imports.len(), &ast[..=index])?;
+                Ok(pattern_value(pattern, &interpreter.globals)
+                    .unwrap_or_else(Value::unit)
+                    .to_string())
+            }
+        }
+    };
+    let result = run();
+    Some(Evaluation {
+        output: interpreter.output,
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+
+    fn parse(text: &str) -> Vec<Anotated<Ast>> {
+        let (_, ast, _) = parse_file(text);
+        ast.expect("the test program parses").0
+    }
+
+    // Runs the last top level declaration of the program
+    fn run(text: &str) -> Evaluation {
+        let ast = parse(text);
+        let offset = ast.last().unwrap().1.start;
+        run_declaration(&[], &ast, offset).unwrap()
+    }
+
+    fn result(text: &str) -> Result<String, String> {
+        run(text).result.map_err(|err| err.message)
+    }
+
+    #[test]
+    fn functions_are_called_with_their_arguments() {
+        let evaluation =
+            run("f := (x) -> x * 2\nmain := () -> {\n    print(f(3), \"six\")\n    f(4) + 1\n}\n");
+        assert_eq!(evaluation.output, vec!["6 six"]);
+        assert_eq!(evaluation.result.unwrap(), "9");
+    }
+
+    #[test]
+    fn loops_assign_to_the_binding_outside() {
+        let text =
+            "main := () -> {\n    i := 0\n    while i < 3 {\n        i = i + 1\n    }\n    i\n}\n";
+        assert_eq!(result(text), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn functions_can_call_themselves() {
+        let text = "fact := (n) -> if n < 2 { 1 } else { n * fact(n - 1) }\nx := fact(5)\n";
+        assert_eq!(result(text), Ok("120".to_string()));
+    }
+
+    #[test]
+    fn and_or_do_not_evaluate_the_right_side_when_the_left_decides() {
+        assert_eq!(
+            result("x := false and missing()\n"),
+            Ok("false".to_string())
+        );
+        assert_eq!(result("x := true or missing()\n"), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn tuples_are_destructured() {
+        assert_eq!(
+            result("(a, b) := (1, (2, \"c\"))\n"),
+            Ok("(1, (2, \"c\"))".to_string())
+        );
+        assert_eq!(
+            result("(a, b) := (1, 2, 3)\n"),
+            Err("can not destructure (1, 2, 3) into 2 names".to_string())
+        );
+    }
+
+    #[test]
+    fn runtime_errors_stop_the_program() {
+        assert_eq!(
+            result("x := 1 + true\n"),
+            Err("can not apply `+` to 1 and true".to_string())
+        );
+        assert_eq!(
+            result("x := 1(2)\n"),
+            Err("1 is not a function".to_string())
+        );
+    }
+
+    #[test]
+    fn a_loop_that_never_ends_is_stopped() {
+        let evaluation = run("main := () -> {\n    while true {\n        print(1)\n    }\n}\n");
+        assert_eq!(
+            evaluation.result.unwrap_err().message,
+            format!("evaluation stopped after {} steps", MAX_STEPS)
+        );
+        assert!(!evaluation.output.is_empty());
+    }
+
+    #[test]
+    fn endless_recursion_is_stopped() {
+        assert_eq!(
+            result("f := (n) -> f(n + 1)\nx := f(0)\n"),
+            Err(format!("more than {} nested calls", MAX_DEPTH))
+        );
+    }
+
+    #[test]
+    fn only_declarations_get_a_code_lens() {
+        let ast = parse("main := () -> 1\nf := (x) -> x\nv := 2\nprint(v)\n");
+        let runnables = find_runnables(&ast)
+            .into_iter()
+            .map(|(_, runnable)| runnable)
+            .collect::<Vec<_>>();
+        assert_eq!(runnables, vec![Runnable::Run, Runnable::Evaluate]);
+    }
+}
@@ -0,0 +1,360 @@
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    token::Span,
+};
+
+use crate::config::LintLevel;
+use crate::const_eval::{fold, Constant};
+use crate::scope::{BindingKind, Scopes};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    Shadowing,
+    UnreachableCode,
+    ConstantCondition,
+    InfiniteLoop,
+    TypedHole,
+}
+
+impl Lint {
+    // Stable name used to configure or suppress the lint
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedParameter => "unused-parameter",
+            Lint::Shadowing => "shadowing",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::ConstantCondition => "constant-condition",
+            Lint::InfiniteLoop => "infinite-loop",
+            Lint::TypedHole => "typed-hole",
+        }
+    }
+
+    // Holes are left on purpose, they only inform
+    pub fn default_level(&self) -> LintLevel {
+        match self {
+            Lint::TypedHole => LintLevel::Info,
+            _ => LintLevel::Warning,
+        }
+    }
+
+    // Unused code is faded out by the editor
+    pub fn is_unnecessary(&self) -> bool {
+        matches!(
+            self,
+            Lint::UnusedVariable | Lint::UnusedParameter | Lint::UnreachableCode
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub span: Span,
+    pub message: String,
+    // Another place in the code the warning refers to
+    pub related: Option<(Span, String)>,
+}
+
+pub fn check_bindings(scopes: &Scopes, warnings: &mut Vec<Warning>) {
+    for (index, binding) in scopes.bindings.iter().enumerate() {
+        // Top level declarations can be used from outside the module
+        if binding.depth == 0 || binding.name.starts_with('_') {
+            continue;
+        }
+        if scopes.references.iter().any(|(_, b)| *b == index) {
+            continue;
+        }
+        let (lint, what) = match binding.kind {
+            BindingKind::Variable => (Lint::UnusedVariable, "variable"),
+            BindingKind::Parameter => (Lint::UnusedParameter, "parameter"),
+        };
+        warnings.push(Warning {
+            lint,
+            span: binding.span.clone(),
+            message: format!("Unused {} `{}`", what, binding.name),
+            related: None,
+        });
+    }
+
+    for (index, outer) in &scopes.shadows {
+        let binding = &scopes.bindings[*index];
+        if binding.kind != BindingKind::Variable || binding.depth == 0 {
+            continue;
+        }
+        let outer = &scopes.bindings[*outer];
+        warnings.push(Warning {
+            lint: Lint::Shadowing,
+            span: binding.span.clone(),
+            message: format!("`{}` shadows a binding from an outer scope", binding.name),
+            related: Some((
+                outer.span.clone(),
+                format!("`{}` declared here", outer.name),
+            )),
+        });
+    }
+}
+
+fn constant_condition(cond: &Anotated<Ast>) -> Option<bool> {
+    match fold(cond)? {
+        Constant::Bool(b) => Some(b),
+        _ => None,
+    }
+}
+
+// Whether evaluating the node never finishes. There is no break or return, so `while true` never exits
+fn diverges(node: &Anotated<Ast>) -> bool {
+    match &node.0 {
+        Ast::While(_, cond, _) => constant_condition(cond) == Some(true) || diverges(cond),
+        Ast::If(_, cond, if_body, _, else_body) => {
+            diverges(cond)
+                || match constant_condition(cond) {
+                    Some(true) => diverges(if_body),
+                    Some(false) => diverges(else_body),
+                    None => diverges(if_body) && diverges(else_body),
+                }
+        }
+        Ast::Block(expresions) | Ast::Tuple(expresions) => expresions.iter().any(diverges),
+        Ast::Declaration(_, _, _, _, Some(value)) => diverges(value),
+        Ast::Binary(l, _, r) => diverges(l) || diverges(r),
+        Ast::Call(caller, args) => diverges(caller) || args.iter().any(diverges),
+        _ => false,
+    }
+}
+
+fn unreachable(span: Span, warnings: &mut Vec<Warning>) {
+    warnings.push(Warning {
+        lint: Lint::UnreachableCode,
+        span,
+        message: "Unreachable code".to_string(),
+        related: None,
+    });
+}
+
+fn check_sequence(expresions: &[Anotated<Ast>], warnings: &mut Vec<Warning>) {
+    for node in expresions {
+        check_dead_code(node, warnings);
+    }
+    if let Some(exit) = expresions.iter().position(diverges) {
+        let rest = &expresions[exit + 1..];
+        if let (Some(first), Some(last)) = (rest.first(), rest.last()) {
+            unreachable(first.1.start..last.1.end, warnings);
+        }
+    }
+}
+
+pub fn check_dead_code(node: &Anotated<Ast>, warnings: &mut Vec<Warning>) {
+    match &node.0 {
+        Ast::Declaration(_, _, _, _, Some(value)) => check_dead_code(value, warnings),
+        Ast::Call(caller, args) => {
+            check_dead_code(caller, warnings);
+            for arg in args {
+                check_dead_code(arg, warnings);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            check_dead_code(l, warnings);
+            check_dead_code(r, warnings);
+        }
+        Ast::While((_, while_span), cond, body) => {
+            match constant_condition(cond) {
+                Some(true) => warnings.push(Warning {
+                    lint: Lint::InfiniteLoop,
+                    span: while_span.clone(),
+                    message: "This loop never ends, its condition is always true".to_string(),
+                    related: None,
+                }),
+                Some(false) => {
+                    warnings.push(Warning {
+                        lint: Lint::ConstantCondition,
+                        span: cond.1.clone(),
+                        message: "Condition is always false, the loop never runs".to_string(),
+                        related: None,
+                    });
+                    unreachable(body.1.clone(), warnings);
+                }
+                None => (),
+            }
+            check_dead_code(cond, warnings);
+            check_dead_code(body, warnings);
+        }
+        Ast::If(_, cond, if_body, else_tk, else_body) => {
+            if let Some(b) = constant_condition(cond) {
+                warnings.push(Warning {
+                    lint: Lint::ConstantCondition,
+                    span: cond.1.clone(),
+                    message: format!("Condition is always {}", b),
+                    related: None,
+                });
+                if !b {
+                    unreachable(if_body.1.clone(), warnings);
+                } else if else_tk.is_some() {
+                    unreachable(else_body.1.clone(), warnings);
+                }
+            }
+            check_dead_code(cond, warnings);
+            check_dead_code(if_body, warnings);
+            check_dead_code(else_body, warnings);
+        }
+        Ast::Tuple(args) => {
+            for arg in args {
+                check_dead_code(arg, warnings);
+            }
+        }
+        Ast::Block(expresions) => check_sequence(expresions, warnings),
+        Ast::Lambda(args, _, body) => {
+            for arg in args {
+                check_dead_code(arg, warnings);
+            }
+            check_dead_code(body, warnings);
+        }
+        _ => (),
+    }
+}
+
+pub fn check_module(ast: &[Anotated<Ast>], warnings: &mut Vec<Warning>) {
+    check_sequence(ast, warnings);
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+    use crate::scope;
+
+    fn parse(text: &str) -> Vec<Anotated<Ast>> {
+        let (_, ast, _) = parse_file(text);
+        ast.expect("the test program parses").0
+    }
+
+    fn binding_warnings(text: &str) -> Vec<(Lint, String)> {
+        let mut warnings = Vec::new();
+        check_bindings(&scope::resolve(&parse(text)), &mut warnings);
+        warnings
+            .into_iter()
+            .map(|warning| (warning.lint, text[warning.span].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reports_unused_locals_and_parameters() {
+        let warnings = binding_warnings("f := (a, b) -> {\n    c := a\n    1\n}\n");
+        assert_eq!(
+            warnings,
+            [
+                (Lint::UnusedParameter, "b".to_string()),
+                (Lint::UnusedVariable, "c".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_level_and_underscore_bindings_are_not_unused() {
+        let warnings = binding_warnings("x := 1\nf := (_a) -> {\n    _b := 2\n    3\n}\n");
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn reports_shadowing_in_a_nested_block() {
+        let text = "x := 1\nf := () -> {\n    x := 2\n    x\n}\n";
+        let mut warnings = Vec::new();
+        check_bindings(&scope::resolve(&parse(text)), &mut warnings);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].lint, Lint::Shadowing);
+        let (related, _) = warnings[0].related.clone().unwrap();
+        assert_eq!(related, 0..1);
+    }
+
+    #[test]
+    fn a_parameter_with_the_name_of_a_global_is_not_shadowing() {
+        let warnings = binding_warnings("x := 1\nf := (x) -> x\n");
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    fn dead_code_warnings(text: &str) -> Vec<(Lint, String)> {
+        let mut warnings = Vec::new();
+        check_module(&parse(text), &mut warnings);
+        warnings
+            .into_iter()
+            .map(|warning| (warning.lint, text[warning.span].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn statements_after_an_infinite_loop_are_unreachable() {
+        let text = "f := () -> {\n    while true {\n        1\n    }\n    a := 2\n    a\n}\n";
+        assert_eq!(
+            dead_code_warnings(text),
+            [
+                (Lint::InfiniteLoop, "while".to_string()),
+                (Lint::UnreachableCode, "a := 2\n    a".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn constant_if_reports_the_branch_that_never_runs() {
+        let text = "f := () -> {\n    if 1 < 2 {\n        1\n    } else {\n        2\n    }\n}\n";
+        assert_eq!(
+            dead_code_warnings(text),
+            [
+                (Lint::ConstantCondition, "1 < 2".to_string()),
+                (Lint::UnreachableCode, "{\n        2\n    }".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn if_false_without_else_reports_its_body() {
+        let warnings = dead_code_warnings("f := () -> {\n    if false {\n        1\n    }\n}\n");
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].0, Lint::ConstantCondition);
+        assert_eq!(
+            warnings[1],
+            (Lint::UnreachableCode, "{\n        1\n    }".to_string())
+        );
+    }
+
+    #[test]
+    fn while_false_never_runs() {
+        let warnings = dead_code_warnings("f := () -> {\n    while false {\n        1\n    }\n}\n");
+        assert_eq!(
+            warnings.iter().map(|(lint, _)| *lint).collect::<Vec<_>>(),
+            [Lint::ConstantCondition, Lint::UnreachableCode]
+        );
+    }
+
+    #[test]
+    fn conditions_that_depend_on_values_are_not_constant() {
+        let text = "f := (x) -> {\n    if x {\n        1\n    } else {\n        2\n    }\n    while x {\n        3\n    }\n    4\n}\n";
+        assert!(dead_code_warnings(text).is_empty());
+    }
+
+    #[test]
+    fn an_if_diverges_only_when_both_branches_do() {
+        let node = |text: &str| parse(text).remove(0);
+        assert!(diverges(&node("while true {\n    1\n}\n")));
+        assert!(!diverges(&node("while false {\n    1\n}\n")));
+        assert!(!diverges(&node(
+            "if x {\n    while true {\n        1\n    }\n} else {\n    2\n}\n"
+        )));
+        assert!(diverges(&node("if x {\n    while true {\n        1\n    }\n} else {\n    while true {\n        2\n    }\n}\n")));
+        assert!(diverges(&node(
+            "if true {\n    while true {\n        1\n    }\n} else {\n    2\n}\n"
+        )));
+        assert_eq!(constant_condition(&node("2 * 3 == 6\n")), Some(true));
+        assert_eq!(constant_condition(&node("1 + 2\n")), None);
+    }
+
+    #[test]
+    fn unused_and_shadowing_have_stable_codes() {
+        assert_eq!(Lint::UnusedVariable.code(), "unused-variable");
+        assert_eq!(Lint::UnusedParameter.code(), "unused-parameter");
+        assert_eq!(Lint::Shadowing.code(), "shadowing");
+        assert!(Lint::UnusedVariable.is_unnecessary());
+        assert!(!Lint::Shadowing.is_unnecessary());
+    }
+}
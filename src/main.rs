@@ -1,44 +1,127 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 
 extern crate lang_frontend;
 use dashmap::DashMap;
 
+mod analysis;
+mod call_hierarchy;
+mod cli;
+mod config;
+mod const_eval;
+mod dap;
+mod database;
+mod dependency_graph;
+mod diagnostics;
+mod document;
+mod dump;
+mod formatter;
+mod holes;
 mod hover;
+mod imports;
 mod inlay_hints;
+mod interpreter;
+mod lints;
+mod quick_fixes;
+mod scope;
+mod selection_range;
 mod semantic_tokens;
-use inlay_hints::get_inlay_hints;
+mod types;
+use analysis::{AnalysisState, Job, Reason, DEBOUNCE};
+use call_hierarchy::{get_calls, is_function};
+use config::{Config, CONFIG_FILE};
+use database::{analyze_file, Analysis, Database};
+use dependency_graph::DependencyGraph;
+use diagnostics::{
+    find_lang_files, result_id, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    WorkspaceDiagnosticParams, WorkspaceDiagnosticReport, WorkspaceDocumentDiagnosticReport,
+    DIAGNOSTICS_DOC,
+};
+use document::DocumentSnapshot;
+use dump::{dump_ast, dump_tokens, dump_type_table};
+use imports::{find_declaration, link, Linked};
+use inlay_hints::{get_inlay_hints, get_value_hints};
+use interpreter::{find_runnables, run_declaration, Runnable};
 use lang_frontend::inferer::Inferer;
 use lang_frontend::types::Type;
-use lang_frontend::*;
-use lang_frontend::{
-    ast::{Anotated, Ast},
-    token::{Spanned, Token},
-};
+use quick_fixes::QuickFix;
 use ropey::Rope;
+use scope::{Binding, Scopes};
+use selection_range::get_selection_spans;
 use semantic_tokens::*;
 use serde::{Deserialize, Serialize};
 
-use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::notification::Notification;
+use serde_json::Value;
+use tower_lsp::jsonrpc::{Error, Result};
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::request::{Request, WorkDoneProgressCreate};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
-#[derive(Debug)]
+// Comandos para ver lo que ha calculado el servidor, el cliente abre el texto en un documento virtual
+const SHOW_SYNTAX_TREE: &str = "lang.showSyntaxTree";
+const SHOW_TYPE_TABLE: &str = "lang.showTypeTable";
+const SHOW_TOKENS: &str = "lang.showTokens";
+// Los comandos de los code lens, sus argumentos son la uri y el inicio de la declaracion
+const RUN: &str = "lang.run";
+const EVALUATE: &str = "lang.evaluate";
+
+// Maximo de archivos cerrados de los que guardamos los diagnosticos
+const MAX_CACHED_DIAGNOSTICS: usize = 1024;
+
+// Todo lo compartido va en Arc, asi cada documento tiene una tarea con su propia copia del Backend
+#[derive(Debug, Clone)]
 struct Backend {
     // Una referencia al cliente que nos permite pasarle mensajes
     client: Client,
-    // Un HashMap de Path -> Modulo
-    ast_map: DashMap<String, (Vec<Anotated<Ast>>, Vec<Type>)>,
-    // Un HashMap de Path -> Source (Rope es un String que se puede modificar rapido)
-    document_map: DashMap<String, Rope>,
-    // Un HashMap de Path -> Lista de Tokens
-    token_map: DashMap<String, Vec<Spanned<Token>>>,
+    // Un HashMap de Path -> Todo lo que sabemos de la ultima version analizada del documento
+    // El snapshot se reemplaza entero, asi nunca vemos el texto de una version con el AST de otra
+    documents: Arc<DashMap<String, Arc<DocumentSnapshot>>>,
+    // Un HashMap de Path -> (resultId, Diagnosticos) de los archivos que no estan abiertos
+    diagnostic_map: Arc<DashMap<String, (String, Vec<Diagnostic>)>>,
+    // Las carpetas del workspace donde buscamos archivos .lang
+    workspace_folders: Arc<RwLock<Vec<PathBuf>>>,
+    // Si el cliente nos pide los diagnosticos dejamos de enviarselos nosotros
+    pull_diagnostics: Arc<AtomicBool>,
+    // La configuracion del proyecto, leida de lang.toml
+    config: Arc<RwLock<Config>>,
+    // Cambia cada vez que se recarga la configuracion, asi los resultId viejos dejan de valer
+    config_generation: Arc<AtomicUsize>,
+    // Un HashMap de Path -> Que version hemos recibido y cual hemos analizado
+    analysis_map: Arc<DashMap<String, Arc<AnalysisState>>>,
+    // Que archivos importa cada archivo del workspace
+    dependency_graph: Arc<RwLock<DependencyGraph>>,
+    // Si el cliente sabe mostrar el progreso de las tareas largas
+    work_done_progress: Arc<AtomicBool>,
+    // Lo que el frontend dice de cada declaracion, se reutiliza mientras no cambien ni ella ni lo que usa
+    database: Arc<Database>,
+    // Un HashMap de Path -> Texto de la ultima version recibida, aunque aun no este analizada
+    pending_map: Arc<DashMap<String, Rope>>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     // Especifica que cosas puede hacer nuesto LSP
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let folders = match (params.workspace_folders, params.root_uri) {
+            (Some(folders), _) => folders.into_iter().map(|folder| folder.uri).collect(),
+            (None, Some(root)) => vec![root],
+            (None, None) => vec![],
+        };
+        *self.workspace_folders.write().unwrap() = folders
+            .into_iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+        let work_done_progress = params
+            .capabilities
+            .window
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.work_done_progress
+            .store(work_done_progress, Ordering::Relaxed);
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
@@ -47,7 +130,26 @@ impl LanguageServer for Backend {
                     TextDocumentSyncKind::FULL,
                 )),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
-                
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                completion_provider: Some(CompletionOptions::default()),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        SHOW_SYNTAX_TREE.to_string(),
+                        SHOW_TYPE_TABLE.to_string(),
+                        SHOW_TOKENS.to_string(),
+                        RUN.to_string(),
+                        EVALUATE.to_string(),
+                    ],
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+
                 // Configuramos los colorcitos de los tokens
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
@@ -90,39 +192,475 @@ impl LanguageServer for Backend {
         // Un pequeño mensaje al cliente
         self.client.log_message(MessageType::LOG, "hovering").await;
 
-        let rope = if let Some(entry) = self.document_map.get(&uri) {
-            entry.value().clone()
+        let pos = params.position;
+
+        // No respondemos con datos viejos, esperamos al analisis de la ultima version
+        let document = if let Some(document) = self.get_latest_snapshot(&uri).await {
+            document
         } else {
             return Ok(None);
         };
 
-        let (ast, type_table) = if let Some(entry) = self.ast_map.get(&uri) {
-            entry.value().clone() // SPEED dont clone
+        let char = document
+            .rope
+            .try_line_to_char(pos.line as usize)
+            .unwrap_or(0);
+        let offset = char + pos.character as usize;
+
+        for declaration in document.analysis.ast.iter() {
+            if let Some(found) = hover::find_match(declaration, offset) {
+                let scopes = scope::resolve(&document.analysis.ast);
+                return Ok(Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: hover::make_hover_text(
+                            &found,
+                            &scopes,
+                            &document.analysis.type_table,
+                            &document.rope,
+                            &document.analysis.imports,
+                        ),
+                    }),
+                    range: span_to_range(&found.span, &document.rope),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let params = params.text_document_position_params;
+        let uri = params.text_document.uri;
+
+        let document = match self.get_latest_snapshot(uri.as_str()).await {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        let offset = match position_to_offset(params.position, &document.rope) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        // Primero buscamos en el propio archivo
+        let scopes = scope::resolve(&document.analysis.ast);
+        if let Some(binding) = scopes.binding_at(offset) {
+            let range = span_to_range(&scopes.bindings[binding].span, &document.rope);
+            return Ok(range.map(|range| GotoDefinitionResponse::Scalar(Location::new(uri, range))));
+        }
+
+        // Si no esta declarado aqui sera de algun archivo importado
+        let name = document.analysis.ast.iter().find_map(|node| {
+            match hover::find_match(node, offset)?.node {
+                hover::HoverNode::Name(name) => Some(name),
+                _ => None,
+            }
+        });
+        let location = name.and_then(|name| {
+            let (module, binding) = find_declaration(&document.analysis.imports, &name)?;
+            let range = span_to_range(&binding.span, &module.rope)?;
+            Some(Location::new(module.uri.clone(), range))
+        });
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let params = params.text_document_position;
+        let uri = params.text_document.uri.to_string();
+
+        if !self.wait_for_analysis(&uri).await {
+            return Ok(None);
+        }
+        let document = if let Some(document) = self.get_snapshot(&uri) {
+            document
         } else {
             return Ok(None);
         };
+        let offset = match position_to_offset(params.position, &document.rope) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
 
-        let pos = params.position;
+        let make_item = |binding: &Binding, file: Option<String>| {
+            let ty = binding
+                .ty
+                .as_ref()
+                .map(|ty| Inferer::get_most_concrete_type(ty, &document.analysis.type_table));
+            let is_function = binding.is_lambda || matches!(ty, Some(Type::Fn(_, _)));
+            CompletionItem {
+                label: binding.name.clone(),
+                kind: Some(if is_function {
+                    CompletionItemKind::FUNCTION
+                } else {
+                    CompletionItemKind::VARIABLE
+                }),
+                detail: match (ty, file) {
+                    (Some(ty), Some(file)) => Some(format!("{} ({})", ty, file)),
+                    (Some(ty), None) => Some(ty.to_string()),
+                    (None, file) => file,
+                },
+                ..CompletionItem::default()
+            }
+        };
 
+        // Lo que se ve desde la posicion, y lo que exportan los archivos importados
+        let scopes = scope::resolve(&document.analysis.ast);
+        let mut items = scopes
+            .visible_at(offset)
+            .into_iter()
+            .map(|binding| make_item(&scopes.bindings[binding], None))
+            .collect::<Vec<_>>();
+        for module in &document.analysis.imports {
+            let file = module
+                .uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(str::to_string);
+            for binding in module.scopes.bindings.iter().filter(|b| b.depth == 0) {
+                if !items.iter().any(|item| item.label == binding.name) {
+                    items.push(make_item(binding, file.clone()));
+                }
+            }
+        }
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    // Para cada posicion devolvemos la cadena de nodos que la contienen, del mas pequeño al mas grande
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let uri = params.text_document.uri.to_string();
+
+        let document = if let Some(document) = self.get_snapshot(&uri) {
+            document
+        } else {
+            return Ok(None);
+        };
+        let rope = &document.rope;
+
+        let ranges = params
+            .positions
+            .into_iter()
+            .map(|pos| {
+                let char = rope.try_line_to_char(pos.line as usize).unwrap_or(0);
+                let offset = char + pos.character as usize;
+
+                let mut spans = Vec::new();
+                for declaration in document.analysis.ast.iter() {
+                    get_selection_spans(declaration, offset, &mut spans);
+                }
+                spans.dedup();
+
+                // Construimos la cadena desde fuera hacia dentro, cada nodo es el padre del siguiente
+                let mut selection: Option<SelectionRange> = None;
+                for span in spans {
+                    if let (Some(start), Some(end)) = (
+                        offset_to_position(span.start, rope),
+                        offset_to_position(span.end, rope),
+                    ) {
+                        selection = Some(SelectionRange {
+                            range: Range::new(start, end),
+                            parent: selection.map(Box::new),
+                        });
+                    }
+                }
+
+                // Si no hay ningun nodo devolvemos un rango vacio, el cliente espera uno por posicion
+                selection.unwrap_or(SelectionRange {
+                    range: Range::new(pos, pos),
+                    parent: None,
+                })
+            })
+            .collect();
+
+        Ok(Some(ranges))
+    }
+
+    // Solo las declaraciones cuyo valor es una lambda se consideran funciones
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let params = params.text_document_position_params;
+        let uri = params.text_document.uri;
+
+        let document = if let Some(document) = self.get_snapshot(uri.as_str()) {
+            document
+        } else {
+            return Ok(None);
+        };
+        let (rope, ast, type_table) = (
+            &document.rope,
+            &document.analysis.ast,
+            &document.analysis.type_table,
+        );
+
+        let pos = params.position;
         let char = rope.try_line_to_char(pos.line as usize).unwrap_or(0);
         let offset = char + pos.character as usize;
 
-        for declaration in ast.iter() {
-            if let Some(t) = hover::find_match(declaration, offset) {
-                return Ok(Some(Hover {
-                    contents: HoverContents::Scalar(MarkedString::String(format!(
-                        "Type: {}",
-                        Inferer::get_most_concrete_type(&t, &type_table)
-                    ))),
-                    range: None,
+        let scopes = scope::resolve(ast);
+        Ok(scopes
+            .binding_at(offset)
+            .filter(|binding| is_function(&scopes, *binding))
+            .and_then(|binding| {
+                make_call_hierarchy_item(&uri, &scopes.bindings[binding], type_table, rope)
+            })
+            .map(|item| vec![item]))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let uri = params.item.uri;
+
+        let document = if let Some(document) = self.get_snapshot(uri.as_str()) {
+            document
+        } else {
+            return Ok(None);
+        };
+        let (rope, ast, type_table) = (
+            &document.rope,
+            &document.analysis.ast,
+            &document.analysis.type_table,
+        );
+
+        let scopes = scope::resolve(ast);
+        let callee = if let Some(callee) = find_call_hierarchy_item(&params.item.data, &scopes) {
+            callee
+        } else {
+            return Ok(None);
+        };
+
+        // Agrupamos las llamadas por la funcion desde la que se hacen
+        let mut callers: Vec<(Option<usize>, Vec<Range>)> = Vec::new();
+        for call in get_calls(ast, &scopes) {
+            if Some(call.callee) != callee {
+                continue;
+            }
+            let range = if let Some(range) = span_to_range(&call.span, rope) {
+                range
+            } else {
+                continue;
+            };
+            match callers
+                .iter_mut()
+                .find(|(caller, _)| *caller == call.caller)
+            {
+                Some((_, ranges)) => ranges.push(range),
+                None => callers.push((call.caller, vec![range])),
+            }
+        }
+
+        Ok(Some(
+            callers
+                .into_iter()
+                .filter_map(|(caller, from_ranges)| {
+                    let from = match caller {
+                        Some(caller) => make_call_hierarchy_item(
+                            &uri,
+                            &scopes.bindings[caller],
+                            type_table,
+                            rope,
+                        )?,
+                        None => make_module_item(&uri, rope),
+                    };
+                    Some(CallHierarchyIncomingCall { from, from_ranges })
+                })
+                .collect(),
+        ))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let uri = params.item.uri;
+
+        let document = if let Some(document) = self.get_snapshot(uri.as_str()) {
+            document
+        } else {
+            return Ok(None);
+        };
+        let (rope, ast, type_table) = (
+            &document.rope,
+            &document.analysis.ast,
+            &document.analysis.type_table,
+        );
+
+        let scopes = scope::resolve(ast);
+        let caller = if let Some(caller) = find_call_hierarchy_item(&params.item.data, &scopes) {
+            caller
+        } else {
+            return Ok(None);
+        };
+
+        // Agrupamos las llamadas por la funcion a la que se llama
+        let mut callees: Vec<(usize, Vec<Range>)> = Vec::new();
+        for call in get_calls(ast, &scopes) {
+            if call.caller != caller {
+                continue;
+            }
+            let range = if let Some(range) = span_to_range(&call.span, rope) {
+                range
+            } else {
+                continue;
+            };
+            match callees
+                .iter_mut()
+                .find(|(callee, _)| *callee == call.callee)
+            {
+                Some((_, ranges)) => ranges.push(range),
+                None => callees.push((call.callee, vec![range])),
+            }
+        }
+
+        Ok(Some(
+            callees
+                .into_iter()
+                .filter_map(|(callee, from_ranges)| {
+                    let to =
+                        make_call_hierarchy_item(&uri, &scopes.bindings[callee], type_table, rope)?;
+                    Some(CallHierarchyOutgoingCall { to, from_ranges })
+                })
+                .collect(),
+        ))
+    }
+
+    // Los arreglos vienen calculados en el data de cada diagnostico
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let mut actions = Vec::new();
+        for diagnostic in params.context.diagnostics {
+            let fixes = match diagnostic
+                .data
+                .clone()
+                .and_then(|data| serde_json::from_value::<Vec<QuickFix>>(data).ok())
+            {
+                Some(fixes) => fixes,
+                None => continue,
+            };
+            let preferred = fixes.len() == 1;
+            for fix in fixes {
+                let mut changes = HashMap::new();
+                changes.insert(uri.clone(), vec![TextEdit::new(fix.range, fix.new_text)]);
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: fix.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit::new(changes)),
+                    is_preferred: Some(preferred),
+                    ..CodeAction::default()
                 }));
             }
         }
 
-        Ok(None)
+        Ok(Some(actions))
     }
 
     // Genera una lista de Token dado un Path
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        // Formateamos lo ultimo que tiene el editor, aunque aun no este analizado
+        let rope = match self.pending_map.get(params.text_document.uri.as_str()) {
+            Some(entry) => entry.value().clone(),
+            None => return Ok(None),
+        };
+        let text = rope.to_string();
+
+        // Si no podemos formatear el archivo lo dejamos como esta
+        let formatted = match formatter::format(&text) {
+            Some(formatted) if formatted != text => formatted,
+            _ => return Ok(None),
+        };
+        let end = offset_to_position(rope.len_chars(), &rope).unwrap_or_default();
+        Ok(Some(vec![TextEdit::new(
+            Range::new(Position::new(0, 0), end),
+            formatted,
+        )]))
+    }
+
+    // Run encima de las funciones sin parametros y Evaluate encima del resto de declaraciones
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri.to_string();
+        if !self.wait_for_analysis(&uri).await {
+            return Ok(None);
+        }
+        let document = match self.get_snapshot(&uri) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        let lenses = find_runnables(&document.analysis.ast)
+            .into_iter()
+            .filter_map(|(span, runnable)| {
+                let (title, command) = match runnable {
+                    Runnable::Run => ("▶ Run", RUN),
+                    Runnable::Evaluate => ("Evaluate", EVALUATE),
+                };
+                Some(CodeLens {
+                    range: span_to_range(&span, &document.rope)?,
+                    command: Some(Command {
+                        title: title.to_string(),
+                        command: command.to_string(),
+                        arguments: Some(vec![Value::String(uri.clone()), Value::from(span.start)]),
+                    }),
+                    data: None,
+                })
+            })
+            .collect();
+        Ok(Some(lenses))
+    }
+
+    // El primer argumento es la uri del documento
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let uri = match params.arguments.first().and_then(|uri| uri.as_str()) {
+            Some(uri) => uri.to_string(),
+            None => return Err(Error::invalid_params("expected the uri of a document")),
+        };
+
+        // Usamos el mismo snapshot que el resto de peticiones
+        if !self.wait_for_analysis(&uri).await {
+            return Ok(None);
+        }
+        let document = match self.get_snapshot(&uri) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        let analysis = &document.analysis;
+
+        let text = match params.command.as_str() {
+            RUN | EVALUATE => {
+                let offset = match params.arguments.get(1).and_then(|offset| offset.as_u64()) {
+                    Some(offset) => offset as usize,
+                    None => {
+                        return Err(Error::invalid_params("expected the start of a declaration"))
+                    }
+                };
+                return Ok(self
+                    .run_declaration(document.clone(), offset)
+                    .await
+                    .map(Value::String));
+            }
+            SHOW_SYNTAX_TREE => dump_ast(&analysis.ast, &analysis.type_table, &document.rope),
+            SHOW_TYPE_TABLE => dump_type_table(&analysis.type_table),
+            SHOW_TOKENS => dump_tokens(&analysis.tokens, &document.rope),
+            command => {
+                return Err(Error::invalid_params(format!(
+                    "unknown command {}",
+                    command
+                )))
+            }
+        };
+        Ok(Some(Value::String(text)))
+    }
+
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
@@ -134,25 +672,23 @@ impl LanguageServer for Backend {
             .log_message(MessageType::LOG, "semantic_token_full")
             .await;
 
-        let rope = if let Some(entry) = self.document_map.get(&uri) {
-            entry.value().clone()
-        } else {
+        if !self.wait_for_analysis(&uri).await {
             return Ok(None);
-        };
+        }
 
-        let (ast, type_table) = if let Some(entry) = self.ast_map.get(&uri) {
-            entry.value().clone() // SPEED dont clone
+        let document = if let Some(document) = self.get_snapshot(&uri) {
+            document
         } else {
             return Ok(None);
         };
 
         let mut tokens = Vec::new();
-        for node in &ast {
-            make_tokens_of_ast(node, &type_table, &mut tokens);
+        for node in &document.analysis.ast {
+            make_tokens_of_ast(node, &document.analysis.type_table, &mut tokens);
         }
         // SPEED make_tokens_semantic relies on the tokens being ordered. Fix that some how
-        tokens.sort_by(|(_, a), (_, b)| a.start.cmp(&b.start));
-        let semantic_tokens = make_tokens_semantic(&tokens, &rope);
+        tokens.sort_by_key(|(_, span)| span.start);
+        let semantic_tokens = make_tokens_semantic(&tokens, &document.rope);
 
         Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
             result_id: None,
@@ -164,49 +700,128 @@ impl LanguageServer for Backend {
         self.client
             .log_message(MessageType::INFO, "initialized!")
             .await;
+
+        // Nuestra version de tower-lsp no tiene diagnosticProvider, asi que lo registramos dinamicamente
+        let registration = Registration {
+            id: "lang-diagnostics".to_string(),
+            method: "textDocument/diagnostic".to_string(),
+            register_options: Some(serde_json::json!({
+                "documentSelector": [{ "language": "lang" }],
+                "identifier": "lang",
+                "interFileDependencies": true,
+                "workspaceDiagnostics": true,
+            })),
+        };
+        // Queremos enterarnos de los cambios en lang.toml y en los archivos que se importan
+        let watcher = Registration {
+            id: "lang-config-watcher".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: Some(serde_json::json!({
+                "watchers": [
+                    { "globPattern": format!("**/{}", CONFIG_FILE) },
+                    { "globPattern": "**/*.lang" },
+                ],
+            })),
+        };
+        if let Err(err) = self
+            .client
+            .register_capability(vec![registration, watcher])
+            .await
+        {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("pull diagnostics not registered: {}", err),
+                )
+                .await;
+        }
+
+        self.reload_config().await;
+        self.scan_workspace().await;
     }
 
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
 
-    async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
         self.client
             .log_message(MessageType::INFO, "workspace folders changed!")
             .await;
+
+        let mut folders = self.workspace_folders.write().unwrap();
+        for removed in params.event.removed {
+            if let Ok(path) = removed.uri.to_file_path() {
+                folders.retain(|folder| folder != &path);
+            }
+        }
+        for added in params.event.added {
+            if let Ok(path) = added.uri.to_file_path() {
+                folders.push(path);
+            }
+        }
     }
 
     async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
         self.client
             .log_message(MessageType::INFO, "configuration changed!")
             .await;
+        self.reload_config().await;
+        self.refresh_diagnostics().await;
     }
 
-    async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
         self.client
             .log_message(MessageType::INFO, "watched files have changed!")
             .await;
+        let config_changed = params.changes.iter().any(|change| {
+            change
+                .uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                == Some(CONFIG_FILE)
+        });
+        if config_changed {
+            self.reload_config().await;
+            self.refresh_diagnostics().await;
+            return;
+        }
+
+        // Si el archivo esta abierto manda lo que hay en el editor, no lo que hay en el disco
+        for change in params.changes {
+            if change.typ == FileChangeType::DELETED {
+                self.dependency_graph.write().unwrap().remove(&change.uri);
+                self.diagnostic_map.remove(change.uri.as_str());
+            }
+            if !self.documents.contains_key(change.uri.as_str()) {
+                self.recheck_dependents(&change.uri).await;
+            }
+        }
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "file opened!")
             .await;
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: params.text_document.text,
-            version: params.text_document.version,
-        })
-        .await
+        self.schedule(
+            TextDocumentItem {
+                uri: params.text_document.uri,
+                text: params.text_document.text,
+                version: params.text_document.version,
+            },
+            Reason::Open,
+        )
     }
 
     async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
-        .await
+        self.schedule(
+            TextDocumentItem {
+                uri: params.text_document.uri,
+                text: std::mem::take(&mut params.content_changes[0].text),
+                version: params.text_document.version,
+            },
+            Reason::Edit,
+        )
     }
 
     async fn did_save(&self, _: DidSaveTextDocumentParams) {
@@ -215,10 +830,32 @@ impl LanguageServer for Backend {
             .await;
     }
 
-    async fn did_close(&self, _: DidCloseTextDocumentParams) {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
         self.client
             .log_message(MessageType::INFO, "file closed!")
             .await;
+
+        // Olvidamos el documento, a partir de ahora se lee del disco si hace falta
+        let uri = params.text_document.uri;
+        let state = self
+            .analysis_map
+            .remove(uri.as_str())
+            .map(|(_, state)| state);
+        // Si se esta guardando un analisis esperamos a que acabe, despues ya no se guarda ninguno
+        let _publishing = match &state {
+            Some(state) => Some(state.publishing().await),
+            None => None,
+        };
+        if let Some(state) = &state {
+            state.close();
+        }
+        self.documents.remove(uri.as_str());
+        self.pending_map.remove(uri.as_str());
+
+        // Quitamos los diagnosticos que publicamos, el cliente ya no los necesita
+        if !self.pull_diagnostics.load(Ordering::Relaxed) {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
     }
 }
 
@@ -227,28 +864,43 @@ struct InlayHintParams {
     path: String,
 }
 
-enum CustomNotification {}
-impl Notification for CustomNotification {
-    type Params = InlayHintParams;
-    const METHOD: &'static str = "custom/notification";
+// Le pide al cliente que vuelva a pedir los diagnosticos
+enum WorkspaceDiagnosticRefresh {}
+impl Request for WorkspaceDiagnosticRefresh {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "workspace/diagnostic/refresh";
 }
+
 struct TextDocumentItem {
     uri: Url,
     text: String,
     version: i32,
 }
 impl Backend {
+    // Compartimos el snapshot con Arc, no hace falta clonar el AST en cada peticion
+    fn get_snapshot(&self, uri: &str) -> Option<Arc<DocumentSnapshot>> {
+        self.documents.get(uri).map(|entry| entry.value().clone())
+    }
+
     // TODO why does it only work after we modify the code the first time?
     async fn inlay_hint(&self, params: InlayHintParams) -> Result<Vec<(usize, usize, String)>> {
         let mut hints = HashMap::new();
-        if let Some(entry) = self.ast_map.get(&params.path) {
-            let ast = &entry.0;
-            let type_table = &entry.1;
+        let mut values = Vec::new();
+        if let Some(document) = self.get_snapshot(&params.path) {
+            let type_table = &document.analysis.type_table;
+            // Cada categoria se puede desactivar en lang.toml
+            let config = self.config.read().unwrap().inlay_hints.clone();
 
-            for node in ast {
-                get_inlay_hints(node, &mut hints);
+            for node in &document.analysis.ast {
+                if config.types {
+                    get_inlay_hints(node, &mut hints);
+                }
+                if config.values {
+                    get_value_hints(node, &mut values);
+                }
             }
-            let inlay_hint_list = hints
+            let mut inlay_hint_list = hints
                 .into_iter()
                 .map(|(k, t)| {
                     (
@@ -258,75 +910,521 @@ impl Backend {
                     )
                 })
                 .collect::<Vec<_>>();
+            // El valor va detras de la expresion, `x := 2 * 21  = 42`
+            inlay_hint_list.extend(
+                values
+                    .into_iter()
+                    .map(|(span, value)| (span.start, span.end, format!("= {}", value))),
+            );
             Ok(inlay_hint_list)
         } else {
             Ok(Vec::new())
         }
     }
 
-    // TODO be more error resilient to fucked AST
-    async fn on_change(&self, params: TextDocumentItem) {
-        // Añadimos el contenido del archivo a nuestro document_map
-        let rope = ropey::Rope::from_str(&params.text);
-        self.document_map
-            .insert(params.uri.to_string(), rope.clone());
+    // Ejecuta la declaracion en otro hilo, lo que imprime y el resultado van al log del cliente
+    async fn run_declaration(
+        &self,
+        document: Arc<DocumentSnapshot>,
+        offset: usize,
+    ) -> Option<String> {
+        let evaluation = tokio::task::spawn_blocking(move || {
+            let analysis = &document.analysis;
+            let imports = analysis
+                .imports
+                .iter()
+                .map(|module| module.ast.as_slice())
+                .collect::<Vec<_>>();
+            run_declaration(&imports, &analysis.ast, offset)
+        })
+        .await
+        .ok()
+        .flatten();
 
-        // Compilamos el archivo
-        let (tokens, ast_and_type_table, errors) = parse_file(params.text.as_str());
+        let evaluation = match evaluation {
+            Some(evaluation) => evaluation,
+            None => {
+                // El code lens era de una version anterior del documento
+                self.client
+                    .show_message(MessageType::WARNING, "The declaration changed, try again")
+                    .await;
+                return None;
+            }
+        };
+        for line in evaluation.output {
+            self.client.log_message(MessageType::INFO, line).await;
+        }
+        match evaluation.result {
+            Ok(value) => {
+                self.client
+                    .log_message(MessageType::INFO, format!("=> {}", value))
+                    .await;
+                Some(value)
+            }
+            Err(err) => {
+                let message = format!("runtime error: {}", err.message);
+                self.client
+                    .log_message(MessageType::ERROR, message.clone())
+                    .await;
+                self.client.show_message(MessageType::ERROR, message).await;
+                None
+            }
+        }
+    }
 
-        // Transformamos nuestros errores en diagnosticos que VS Code puede usar
-        let diagnostics = errors
-            .into_iter()
-            .map(|item| {
-                let (message, span) = match item.reason() {
-                    chumsky::error::SimpleReason::Unclosed { span, delimiter } => {
-                        (format!("Unclosed delimiter {}", delimiter), span.clone())
-                    }
-                    chumsky::error::SimpleReason::Unexpected => (
-                        format!(
-                            "{}, expected {}",
-                            if item.found().is_some() {
-                                "Unexpected token in input"
-                            } else {
-                                "Unexpected end of input"
-                            },
-                            if item.expected().len() == 0 {
-                                "something else".to_string()
-                            } else {
-                                item.expected()
-                                    .map(|expected| match expected {
-                                        Some(expected) => expected.to_string(),
-                                        None => "end of input".to_string(),
-                                    })
-                                    .collect::<Vec<_>>()
-                                    .join(", ")
-                            }
-                        ),
-                        item.span(),
-                    ),
-                    chumsky::error::SimpleReason::Custom(msg) => (msg.to_string(), item.span()),
-                };
+    // El documento que explica los codigos de los diagnosticos, los enlaces de codeDescription
+    // lo abren en el editor
+    async fn diagnostics_doc(&self) -> Result<String> {
+        Ok(DIAGNOSTICS_DOC.to_string())
+    }
 
-                let start_position = offset_to_position(span.start, &rope).unwrap();
-                let end_position = offset_to_position(span.end, &rope).unwrap();
+    async fn document_diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReport> {
+        self.start_pull_diagnostics().await;
 
-                Diagnostic::new_simple(Range::new(start_position, end_position), message)
+        let uri = params.text_document.uri;
+        let (id, items) = match self.load_diagnostics(&uri).await {
+            Some(entry) => entry,
+            None => {
+                return Ok(DocumentDiagnosticReport::Full {
+                    result_id: None,
+                    items: vec![],
+                })
+            }
+        };
+
+        if params.previous_result_id.as_ref() == Some(&id) {
+            Ok(DocumentDiagnosticReport::Unchanged { result_id: id })
+        } else {
+            Ok(DocumentDiagnosticReport::Full {
+                result_id: Some(id),
+                items,
             })
+        }
+    }
+
+    // Diagnosticos de todos los archivos .lang del workspace, esten abiertos o no
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReport> {
+        self.start_pull_diagnostics().await;
+
+        let mut files = Vec::new();
+        for folder in self.workspace_folders.read().unwrap().iter() {
+            find_lang_files(folder, &mut files);
+        }
+
+        let uris = files
+            .into_iter()
+            .filter_map(|path| Url::from_file_path(path).ok())
             .collect::<Vec<_>>();
 
-        // Enviamos los diagnosticos
-        self.client
-            .publish_diagnostics(params.uri.clone(), diagnostics, Some(params.version))
+        // Olvidamos los archivos que ya no existen
+        self.diagnostic_map
+            .retain(|key, _| uris.iter().any(|uri| uri.as_str() == key));
+
+        // Los archivos cerrados se leen del disco, eso no se hace en el runtime
+        let backend = self.clone();
+        let previous_result_ids = params.previous_result_ids;
+        let items = tokio::task::spawn_blocking(move || {
+            uris.into_iter()
+                .filter_map(|uri| {
+                    let (id, items) = backend.get_diagnostics(&uri)?;
+                    let previous = previous_result_ids
+                        .iter()
+                        .find(|previous| previous.uri == uri);
+                    let report = if previous.map(|previous| &previous.value) == Some(&id) {
+                        DocumentDiagnosticReport::Unchanged { result_id: id }
+                    } else {
+                        DocumentDiagnosticReport::Full {
+                            result_id: Some(id),
+                            items,
+                        }
+                    };
+                    Some(WorkspaceDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        report,
+                    })
+                })
+                .collect()
+        })
+        .await
+        .unwrap_or_default();
+
+        Ok(WorkspaceDiagnosticReport { items })
+    }
+
+    // Leemos todo el workspace al arrancar para saber que archivo depende de cual
+    async fn scan_workspace(&self) {
+        let mut files = Vec::new();
+        for folder in self.workspace_folders.read().unwrap().iter() {
+            find_lang_files(folder, &mut files);
+        }
+        let uris = files
+            .into_iter()
+            .filter_map(|path| Url::from_file_path(path).ok())
+            .collect::<Vec<_>>();
+
+        let token = NumberOrString::String("lang-workspace-scan".to_string());
+        let progress = self.work_done_progress.load(Ordering::Relaxed)
+            && self
+                .client
+                .send_custom_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                    token: token.clone(),
+                })
+                .await
+                .is_ok();
+        let report = |progress: WorkDoneProgress| {
+            self.client
+                .send_custom_notification::<Progress>(ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(progress),
+                })
+        };
+
+        if progress {
+            report(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Checking workspace".to_string(),
+                cancellable: Some(false),
+                message: Some(format!("0/{} files", uris.len())),
+                percentage: Some(0),
+            }))
             .await;
+        }
+        for (i, uri) in uris.iter().enumerate() {
+            self.load_diagnostics(uri).await;
+            if progress {
+                report(WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: Some(false),
+                    message: Some(format!("{}/{} files", i + 1, uris.len())),
+                    percentage: Some(((i + 1) * 100 / uris.len()) as u32),
+                }))
+                .await;
+            }
+            // Dejamos que se atiendan otras peticiones entre archivo y archivo
+            tokio::task::yield_now().await;
+        }
+        if progress {
+            report(WorkDoneProgress::End(WorkDoneProgressEnd { message: None })).await;
+        }
+    }
 
-        if let Some(ast_and_type_table) = ast_and_type_table {
-            self.ast_map
-                .insert(params.uri.to_string(), ast_and_type_table);
+    // La primera vez que el cliente pide diagnosticos borramos los que le enviamos nosotros
+    async fn start_pull_diagnostics(&self) {
+        if self.pull_diagnostics.swap(true, Ordering::Relaxed) {
+            return;
         }
+        let uris = self
+            .documents
+            .iter()
+            .filter_map(|entry| Url::parse(entry.key()).ok())
+            .collect::<Vec<_>>();
+        for uri in uris {
+            self.client.publish_diagnostics(uri, vec![], None).await;
+        }
+    }
 
-        if let Some(tokens) = tokens {
-            self.token_map.insert(params.uri.to_string(), tokens);
+    // get_diagnostics puede leer del disco y compilar, asi que lo hacemos fuera del runtime
+    async fn load_diagnostics(&self, uri: &Url) -> Option<(String, Vec<Diagnostic>)> {
+        let backend = self.clone();
+        let uri = uri.clone();
+        tokio::task::spawn_blocking(move || backend.get_diagnostics(&uri))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    // Los diagnosticos de los archivos abiertos ya estan calculados, los demas se leen del disco
+    fn get_diagnostics(&self, uri: &Url) -> Option<(String, Vec<Diagnostic>)> {
+        if let Some(document) = self.get_snapshot(uri.as_str()) {
+            return Some((
+                document.result_id.clone(),
+                document.analysis.diagnostics.clone(),
+            ));
         }
+
+        let text = std::fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+        let linked = self.link_imports(uri, &text);
+        self.dependency_graph
+            .write()
+            .unwrap()
+            .set_imports(uri, linked.imported_uris());
+        let id = result_id(&linked.text, self.config_generation.load(Ordering::Relaxed));
+        if let Some(entry) = self.diagnostic_map.get(&uri.to_string()) {
+            if entry.value().0 == id {
+                return Some(entry.value().clone());
+            }
+        }
+
+        let diagnostics =
+            analyze_file(&self.database, uri, &linked, &self.config.read().unwrap()).diagnostics;
+        // Es solo una cache, si crece demasiado empezamos de cero
+        if self.diagnostic_map.len() >= MAX_CACHED_DIAGNOSTICS {
+            self.diagnostic_map.clear();
+        }
+        self.diagnostic_map
+            .insert(uri.to_string(), (id.clone(), diagnostics.clone()));
+        Some((id, diagnostics))
+    }
+
+    async fn reload_config(&self) {
+        let folders = self.workspace_folders.read().unwrap().clone();
+        match Config::load(&folders) {
+            Ok(config) => *self.config.write().unwrap() = config,
+            Err(err) => {
+                self.client
+                    .show_message(MessageType::WARNING, format!("Invalid {}", err))
+                    .await
+            }
+        }
+        self.config_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Volvemos a analizar los archivos abiertos, por ejemplo cuando cambia la configuracion
+    async fn refresh_diagnostics(&self) {
+        // Si hay una version mas nueva pendiente ya usara la configuracion nueva
+        let documents = self
+            .documents
+            .iter()
+            .filter(|entry| {
+                self.analysis_map
+                    .get(entry.key())
+                    .is_some_and(|state| state.is_latest(entry.value().version))
+            })
+            .filter_map(|entry| {
+                let uri = Url::parse(entry.key()).ok()?;
+                Some((uri, entry.value().rope.to_string(), entry.value().version))
+            })
+            .collect::<Vec<_>>();
+        for (uri, text, version) in &documents {
+            let item = TextDocumentItem {
+                uri: uri.clone(),
+                text: text.clone(),
+                version: *version,
+            };
+            self.schedule(item, Reason::Recheck);
+        }
+
+        if self.pull_diagnostics.load(Ordering::Relaxed) {
+            for (uri, _, _) in &documents {
+                self.wait_for_analysis(uri.as_str()).await;
+            }
+            let _ = self
+                .client
+                .send_custom_request::<WorkspaceDiagnosticRefresh>(())
+                .await;
+        }
+    }
+
+    // El estado del documento, la primera vez tambien arranca la tarea que lo analiza
+    fn analysis_state(&self, uri: &Url) -> Arc<AnalysisState> {
+        let mut created = None;
+        let state = self
+            .analysis_map
+            .entry(uri.to_string())
+            .or_insert_with(|| {
+                let state = Arc::new(AnalysisState::default());
+                created = Some(state.clone());
+                state
+            })
+            .value()
+            .clone();
+        if let Some(state) = created {
+            tokio::spawn(self.clone().analysis_task(uri.clone(), state));
+        }
+        state
+    }
+
+    // El snapshot de la ultima version recibida. Una declaracion que no ha cambiado puede tener
+    // otro tipo, la inferencia comparte la tabla de tipos con el resto del archivo
+    async fn get_latest_snapshot(&self, uri: &str) -> Option<Arc<DocumentSnapshot>> {
+        if !self.wait_for_analysis(uri).await {
+            return None;
+        }
+        self.get_snapshot(uri)
+    }
+
+    // Espera a que este analizada la ultima version que hemos recibido del documento
+    async fn wait_for_analysis(&self, uri: &str) -> bool {
+        let state = match self.analysis_map.get(uri) {
+            Some(entry) => entry.value().clone(),
+            None => return false,
+        };
+        state.wait().await
+    }
+
+    // Pone los archivos importados delante del documento, leyendolos del editor si estan abiertos
+    fn link_imports(&self, uri: &Url, text: &str) -> Linked {
+        let roots = self.workspace_folders.read().unwrap().clone();
+        let read = |uri: &Url| match self.get_snapshot(uri.as_str()) {
+            Some(document) => Some(document.rope.to_string()),
+            None => std::fs::read_to_string(uri.to_file_path().ok()?).ok(),
+        };
+        link(uri, text, &roots, &read)
+    }
+
+    // Guarda la version como la ultima recibida y se la pasa a la tarea del documento
+    fn schedule(&self, params: TextDocumentItem, reason: Reason) {
+        // Volver a comprobar una version no cambia el texto, y si ya hay otra mas nueva no se toca
+        if reason != Reason::Recheck {
+            self.pending_map
+                .insert(params.uri.to_string(), Rope::from_str(&params.text));
+        }
+        self.analysis_state(&params.uri).schedule(Job {
+            text: params.text,
+            version: params.version,
+            reason,
+        });
+    }
+
+    // Cada documento abierto tiene una tarea que analiza sus versiones una detras de otra. Solo
+    // ve la ultima version que ha llegado, asi escribir rapido no acumula analisis
+    async fn analysis_task(self, uri: Url, state: Arc<AnalysisState>) {
+        let mut jobs = state.jobs();
+        loop {
+            if jobs.changed().await.is_err() {
+                return;
+            }
+            let mut job = match jobs.borrow_and_update().clone() {
+                Some(job) => job,
+                None => return,
+            };
+
+            // Esperamos a que se deje de escribir, cada version nueva vuelve a empezar la espera
+            while job.reason == Reason::Edit {
+                match tokio::time::timeout(DEBOUNCE, jobs.changed()).await {
+                    Err(_) => break,
+                    Ok(Err(_)) => return,
+                    Ok(Ok(())) => match jobs.borrow_and_update().clone() {
+                        Some(next) => job = next,
+                        None => return,
+                    },
+                }
+            }
+
+            let recheck = job.reason != Reason::Recheck;
+            if self.analyze(&uri, &state, job).await && recheck {
+                self.recheck_dependents(&uri).await;
+            }
+        }
+    }
+
+    // Solo se vuelven a comprobar los archivos que importan este, directa o indirectamente
+    async fn recheck_dependents(&self, uri: &Url) {
+        let dependents = self.dependency_graph.read().unwrap().dependents(uri);
+        if dependents.is_empty() {
+            return;
+        }
+        let mut open = Vec::new();
+        for dependent in dependents {
+            match self.get_snapshot(dependent.as_str()) {
+                Some(document) => {
+                    let item = TextDocumentItem {
+                        uri: dependent.clone(),
+                        text: document.rope.to_string(),
+                        version: document.version,
+                    };
+                    self.schedule(item, Reason::Recheck);
+                    open.push(dependent);
+                }
+                // Los cerrados se recalculan ya, asi la cache esta lista cuando nos los pidan
+                None => {
+                    self.load_diagnostics(&dependent).await;
+                }
+            }
+        }
+
+        if self.pull_diagnostics.load(Ordering::Relaxed) {
+            for uri in &open {
+                self.wait_for_analysis(uri.as_str()).await;
+            }
+            let _ = self
+                .client
+                .send_custom_request::<WorkspaceDiagnosticRefresh>(())
+                .await;
+        }
+    }
+
+    // TODO be more error resilient to fucked AST
+    // Devuelve si se ha actualizado el snapshot del documento
+    async fn analyze(&self, uri: &Url, state: &AnalysisState, job: Job) -> bool {
+        // Leer los imports y compilar bloquea, se hace en otro hilo. No se abandona a medias, si
+        // llega una version mas nueva la tarea la analiza al terminar esta
+        let backend = self.clone();
+        let params = TextDocumentItem {
+            uri: uri.clone(),
+            text: job.text,
+            version: job.version,
+        };
+        let (uri, text) = (params.uri.clone(), params.text.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let linked = backend.link_imports(&uri, &text);
+            backend
+                .dependency_graph
+                .write()
+                .unwrap()
+                .set_imports(&uri, linked.imported_uris());
+
+            let id = result_id(
+                &linked.text,
+                backend.config_generation.load(Ordering::Relaxed),
+            );
+            // Solo se vuelven a inferir las declaraciones que han cambiado, el resto sale de la base de datos
+            let config = backend.config.read().unwrap().clone();
+            let analysis = Arc::new(analyze_file(&backend.database, &uri, &linked, &config));
+            (analysis, id)
+        })
+        .await;
+        let (analysis, id) = match result {
+            Ok(result) => result,
+            Err(_) => {
+                // El frontend ha fallado, no hay nada mejor que lo que ya teniamos
+                state.finish(params.version);
+                return false;
+            }
+        };
+        self.update_snapshot(&params, state, analysis, id).await
+    }
+
+    // Cambiamos el snapshot entero de una vez y avisamos al cliente. Devuelve false si el
+    // documento se ha cerrado o ha cambiado mientras lo analizabamos
+    async fn update_snapshot(
+        &self,
+        params: &TextDocumentItem,
+        state: &AnalysisState,
+        analysis: Arc<Analysis>,
+        result_id: String,
+    ) -> bool {
+        let _publishing = state.publishing().await;
+        if !state.is_latest(params.version) {
+            return false;
+        }
+
+        let document = Arc::new(DocumentSnapshot {
+            version: params.version,
+            rope: Rope::from_str(&params.text),
+            analysis,
+            result_id,
+        });
+        self.documents
+            .insert(params.uri.to_string(), document.clone());
+        state.finish(params.version);
+
+        // Enviamos los diagnosticos, salvo que el cliente ya nos los pida el
+        if !self.pull_diagnostics.load(Ordering::Relaxed) {
+            self.client
+                .publish_diagnostics(
+                    params.uri.clone(),
+                    document.analysis.diagnostics.clone(),
+                    Some(document.version),
+                )
+                .await;
+        }
+        true
     }
 }
 
@@ -334,18 +1432,35 @@ impl Backend {
 async fn main() {
     env_logger::init();
 
+    // Con argumentos somos una herramienta de linea de comandos, sin ellos hablamos LSP por stdio
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
     // Creo el server e inicialido el Backend
     let (service, socket) = LspService::build(|client| Backend {
         client,
-        ast_map: DashMap::new(),
-        document_map: DashMap::new(),
-        token_map: DashMap::new(),
+        documents: Arc::new(DashMap::new()),
+        diagnostic_map: Arc::new(DashMap::new()),
+        workspace_folders: Arc::new(RwLock::new(Vec::new())),
+        pull_diagnostics: Arc::new(AtomicBool::new(false)),
+        config: Arc::new(RwLock::new(Config::default())),
+        config_generation: Arc::new(AtomicUsize::new(0)),
+        analysis_map: Arc::new(DashMap::new()),
+        dependency_graph: Arc::new(RwLock::new(DependencyGraph::default())),
+        work_done_progress: Arc::new(AtomicBool::new(false)),
+        database: Arc::new(Database::default()),
+        pending_map: Arc::new(DashMap::new()),
     })
     // Añado un metodo que se llama inlay_hit, esto es lo que hace que aparezcan tipos en las variables
     .custom_method("custom/inlay_hint", Backend::inlay_hint)
+    .custom_method("custom/diagnostics_doc", Backend::diagnostics_doc)
+    .custom_method("textDocument/diagnostic", Backend::document_diagnostic)
+    .custom_method("workspace/diagnostic", Backend::workspace_diagnostic)
     .finish();
     Server::new(stdin, stdout, socket).serve(service).await;
 }
@@ -357,3 +1472,72 @@ fn offset_to_position(offset: usize, rope: &Rope) -> Option<Position> {
     let column = offset - first_char;
     Some(Position::new(line as u32, column as u32))
 }
+
+fn position_to_offset(position: Position, rope: &Rope) -> Option<usize> {
+    let first_char = rope.try_line_to_char(position.line as usize).ok()?;
+    Some(first_char + position.character as usize)
+}
+
+fn span_to_range(span: &std::ops::Range<usize>, rope: &Rope) -> Option<Range> {
+    Some(Range::new(
+        offset_to_position(span.start, rope)?,
+        offset_to_position(span.end, rope)?,
+    ))
+}
+
+// El data de un CallHierarchyItem es el offset del nombre de la funcion, o null para el modulo
+fn find_call_hierarchy_item(
+    data: &Option<serde_json::Value>,
+    scopes: &Scopes,
+) -> Option<Option<usize>> {
+    match data.as_ref().and_then(|data| data.as_u64()) {
+        Some(offset) => scopes
+            .bindings
+            .iter()
+            .enumerate()
+            .position(|(i, binding)| binding.span.start as u64 == offset && is_function(scopes, i))
+            .map(Some),
+        None => Some(None),
+    }
+}
+
+fn make_call_hierarchy_item(
+    uri: &Url,
+    binding: &Binding,
+    type_table: &[Type],
+    rope: &Rope,
+) -> Option<CallHierarchyItem> {
+    Some(CallHierarchyItem {
+        name: binding.name.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: binding
+            .ty
+            .as_ref()
+            .map(|t| Inferer::get_most_concrete_type(t, type_table).to_string()),
+        uri: uri.clone(),
+        range: span_to_range(&binding.decl_span, rope)?,
+        selection_range: span_to_range(&binding.span, rope)?,
+        data: Some(serde_json::Value::from(binding.span.start)),
+    })
+}
+
+// El codigo que no esta dentro de ninguna funcion se representa con el propio archivo
+fn make_module_item(uri: &Url, rope: &Rope) -> CallHierarchyItem {
+    let name = uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+        .to_string();
+    let end = offset_to_position(rope.len_chars(), rope).unwrap_or_default();
+    CallHierarchyItem {
+        name,
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: Range::new(Position::default(), end),
+        selection_range: Range::new(Position::default(), Position::default()),
+        data: None,
+    }
+}
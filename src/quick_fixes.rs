@@ -0,0 +1,234 @@
+use std::hash::Hash;
+
+use chumsky::error::{Simple, SimpleReason};
+use lang_frontend::{
+    ast::{Anotated, Ast},
+    inferer::Inferer,
+    token::{Span, Token},
+    types::Type,
+};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::types::{fits, literal_type};
+use crate::{offset_to_position, span_to_range};
+
+// An edit stored in the data of a diagnostic, code actions turn it into a WorkspaceEdit
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickFix {
+    pub title: String,
+    pub range: Range,
+    pub new_text: String,
+}
+
+fn insert(title: String, position: Position, new_text: String) -> QuickFix {
+    QuickFix {
+        title,
+        range: Range::new(position, position),
+        new_text,
+    }
+}
+
+fn closing_delimiter(delimiter: &str) -> Option<&'static str> {
+    match delimiter {
+        "(" => Some(")"),
+        "[" => Some("]"),
+        "{" => Some("}"),
+        "\"" => Some("\""),
+        _ => None,
+    }
+}
+
+pub fn parse_error_fixes<T: std::fmt::Display + Hash + Eq>(
+    item: &Simple<T>,
+    rope: &Rope,
+) -> Vec<QuickFix> {
+    let position = match offset_to_position(item.span().start, rope) {
+        Some(position) => position,
+        None => return vec![],
+    };
+    match item.reason() {
+        SimpleReason::Unclosed { delimiter, .. } => {
+            match closing_delimiter(&delimiter.to_string()) {
+                Some(closing) => vec![insert(
+                    format!("Insert missing `{}`", closing),
+                    position,
+                    closing.to_string(),
+                )],
+                None => vec![],
+            }
+        }
+        SimpleReason::Unexpected if item.expected().len() == 1 => match item.expected().next() {
+            Some(Some(expected)) => vec![insert(
+                format!("Insert `{}`", expected),
+                position,
+                expected.to_string(),
+            )],
+            _ => vec![],
+        },
+        // Type errors only have a message, their fixes come from the AST in type_mismatch_fixes
+        SimpleReason::Unexpected | SimpleReason::Custom(_) => vec![],
+    }
+}
+
+// A value whose type is not the one the place it is used in needs
+struct Mismatch<'a> {
+    value: &'a Anotated<Ast>,
+    expected: Type,
+    found: Type,
+}
+
+fn concrete(ty: &Option<Type>, type_table: &[Type]) -> Option<Type> {
+    ty.as_ref()
+        .map(|ty| Inferer::get_most_concrete_type(ty, type_table))
+}
+
+fn check_value<'a>(
+    value: &'a Anotated<Ast>,
+    expected: Option<Type>,
+    type_table: &[Type],
+    mismatches: &mut Vec<Mismatch<'a>>,
+) {
+    if let (Some(expected), Some(found)) = (expected, concrete(&value.2, type_table)) {
+        if !fits(&found, &expected) {
+            mismatches.push(Mismatch {
+                value,
+                expected,
+                found,
+            });
+        }
+    }
+}
+
+// The two places where the AST says what type is expected: the annotation of a declaration and
+// the parameters of the function being called
+fn collect_mismatches<'a>(
+    (node, _, _): &'a Anotated<Ast>,
+    type_table: &[Type],
+    mismatches: &mut Vec<Mismatch<'a>>,
+) {
+    match node {
+        Ast::Error | Ast::Literal(_) | Ast::Variable(_) | Ast::Coment(_) | Ast::Type(_) => (),
+        Ast::Declaration(_, _, annotation, _, value) => {
+            if let (Some(annotation), Some(value)) = (annotation, value) {
+                let expected = match &annotation.0 {
+                    Ast::Type(ty) => Some(Inferer::get_most_concrete_type(ty, type_table)),
+                    _ => concrete(&annotation.2, type_table),
+                };
+                check_value(value, expected, type_table, mismatches);
+            }
+            if let Some(value) = value {
+                collect_mismatches(value, type_table, mismatches);
+            }
+        }
+        Ast::Call(caller, args) => {
+            if let Some(Type::Fn(params, _)) = concrete(&caller.2, type_table) {
+                if params.len() == args.len() {
+                    for (arg, param) in args.iter().zip(params) {
+                        check_value(arg, Some(param), type_table, mismatches);
+                    }
+                }
+            }
+            collect_mismatches(caller, type_table, mismatches);
+            for arg in args {
+                collect_mismatches(arg, type_table, mismatches);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            collect_mismatches(l, type_table, mismatches);
+            collect_mismatches(r, type_table, mismatches);
+        }
+        Ast::While(_, cond, body) => {
+            collect_mismatches(cond, type_table, mismatches);
+            collect_mismatches(body, type_table, mismatches);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            collect_mismatches(cond, type_table, mismatches);
+            collect_mismatches(if_body, type_table, mismatches);
+            collect_mismatches(else_body, type_table, mismatches);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                collect_mismatches(arg, type_table, mismatches);
+            }
+        }
+        Ast::Lambda(_, _, body) => collect_mismatches(body, type_table, mismatches),
+    }
+}
+
+// The same value written as a literal of other kinds, with the token it would be
+fn literal_conversions(token: &Token) -> Vec<(Token, String)> {
+    match token {
+        Token::Number(n) => vec![(Token::Text(n.clone()), format!("\"{}\"", n))],
+        Token::Bool(b) => vec![(Token::Text(b.to_string()), format!("\"{}\"", b))],
+        Token::Text(text) => {
+            let text = text.trim();
+            let mut conversions = Vec::new();
+            if text.parse::<f64>().is_ok() {
+                conversions.push((Token::Number(text.to_string()), text.to_string()));
+            }
+            if let Ok(b) = text.parse::<bool>() {
+                conversions.push((Token::Bool(b), text.to_string()));
+            }
+            conversions
+        }
+        _ => vec![],
+    }
+}
+
+fn mismatch_fixes(mismatch: &Mismatch, rope: &Rope) -> Vec<QuickFix> {
+    let (value, span, _) = mismatch.value;
+    let (range, source) = match (span_to_range(span, rope), rope.get_slice(span.clone())) {
+        (Some(range), Some(source)) => (range, source.to_string()),
+        _ => return vec![],
+    };
+
+    let mut fixes = Vec::new();
+    // Convert a literal, the candidate is kept only if its type is the expected one
+    if let Ast::Literal((token, _)) = value {
+        for (converted, new_text) in literal_conversions(token) {
+            if literal_type(&converted).as_ref() == Some(&mismatch.expected) {
+                fixes.push(QuickFix {
+                    title: format!("Convert `{}` to `{}`", mismatch.found, mismatch.expected),
+                    range,
+                    new_text,
+                });
+            }
+        }
+    }
+    // Wrap a function without parameters in a call when what it returns is what was expected
+    if let Type::Fn(params, ret) = &mismatch.found {
+        if params.is_empty() && fits(ret, &mismatch.expected) {
+            let callee = match value {
+                Ast::Variable(_) | Ast::Call(..) => source,
+                _ => format!("({})", source),
+            };
+            fixes.push(QuickFix {
+                title: format!("Call it to get `{}`", mismatch.expected),
+                range,
+                new_text: format!("{}()", callee),
+            });
+        }
+    }
+    fixes
+}
+
+// Fixes for every value whose type does not match, with the span of the value so they can be
+// attached to the error the frontend reports there
+pub fn type_mismatch_fixes(
+    ast: &[Anotated<Ast>],
+    type_table: &[Type],
+    rope: &Rope,
+) -> Vec<(Span, Vec<QuickFix>)> {
+    let mut mismatches = Vec::new();
+    for node in ast {
+        collect_mismatches(node, type_table, &mut mismatches);
+    }
+    mismatches
+        .iter()
+        .map(|mismatch| (mismatch.value.1.clone(), mismatch_fixes(mismatch, rope)))
+        .filter(|(_, fixes)| !fixes.is_empty())
+        .collect()
+}
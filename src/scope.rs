@@ -0,0 +1,394 @@
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    token::{Span, Spanned, Token},
+    types::Type,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Variable,
+    Parameter,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    // Span of the name in the pattern
+    pub span: Span,
+    // Span of the whole declaration (or parameter)
+    pub decl_span: Span,
+    pub kind: BindingKind,
+    pub ty: Option<Type>,
+    pub is_lambda: bool,
+    // 0 for top level declarations
+    pub depth: usize,
+    // Block or lambda the binding is visible in, None for top level declarations
+    pub scope: Option<Span>,
+    // Declared by a statement of the top level or of a block, the only ones with doc comments
+    pub statement: bool,
+}
+
+// Result of resolving every variable use in a module to the binding it refers to
+#[derive(Debug, Default, Clone)]
+pub struct Scopes {
+    pub bindings: Vec<Binding>,
+    // Span of the use -> index in bindings
+    pub references: Vec<(Span, usize)>,
+    // (binding, outer binding with the same name it hides)
+    pub shadows: Vec<(usize, usize)>,
+    // Uses of names nothing in the module declares, builtins or names of another module
+    pub unresolved: Vec<Spanned<String>>,
+}
+
+impl Scopes {
+    pub fn binding_at(&self, pos: usize) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|binding| binding.span.contains(&pos))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|(span, _)| span.contains(&pos))
+                    .map(|(_, binding)| *binding)
+            })
+    }
+
+    // Bindings that can be used at pos, the innermost one for each name
+    pub fn visible_at(&self, pos: usize) -> Vec<usize> {
+        let mut visible: Vec<usize> = Vec::new();
+        for (index, binding) in self.bindings.iter().enumerate() {
+            let in_scope = match &binding.scope {
+                Some(scope) => scope.contains(&pos) && binding.span.end <= pos,
+                None => true,
+            };
+            if !in_scope {
+                continue;
+            }
+            // A later top level declaration does not hide a local one
+            let hidden = binding.scope.is_none()
+                && visible.iter().any(|other| {
+                    self.bindings[*other].name == binding.name
+                        && self.bindings[*other].scope.is_some()
+                });
+            if hidden {
+                continue;
+            }
+            visible.retain(|other| self.bindings[*other].name != binding.name);
+            visible.push(index);
+        }
+        visible
+    }
+
+    pub fn resolve_use(&self, span: &Span) -> Option<usize> {
+        self.references
+            .iter()
+            .find(|(use_span, _)| use_span == span)
+            .map(|(_, binding)| *binding)
+    }
+}
+
+struct Resolver {
+    scopes: Scopes,
+    stack: Vec<Vec<(String, usize)>>,
+    // Span of every scope in the stack but the top level one
+    spans: Vec<Span>,
+    // Set right before visiting a statement of the top level or of a block
+    statement: bool,
+}
+
+impl Resolver {
+    fn declare(&mut self, mut binding: Binding) -> usize {
+        let index = self.scopes.bindings.len();
+        binding.depth = self.stack.len() - 1;
+        binding.scope = self.spans.last().cloned();
+        if let Some(outer) = self.lookup(&binding.name) {
+            let in_current_scope = self.stack.last().unwrap().iter().any(|(_, i)| *i == outer);
+            if !in_current_scope {
+                self.scopes.shadows.push((index, outer));
+            }
+        }
+        self.stack
+            .last_mut()
+            .unwrap()
+            .push((binding.name.clone(), index));
+        self.scopes.bindings.push(binding);
+        index
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.stack
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|(n, _)| n == name))
+            .map(|(_, index)| *index)
+    }
+
+    fn declare_pattern(
+        &mut self,
+        pattern: &Anotated<Pattern>,
+        decl_span: &Span,
+        kind: BindingKind,
+        is_lambda: bool,
+        statement: bool,
+    ) {
+        match &pattern.0 {
+            Pattern::Var((name, span)) => {
+                self.declare(Binding {
+                    name: name.clone(),
+                    span: span.clone(),
+                    decl_span: decl_span.clone(),
+                    kind,
+                    ty: pattern.2.clone(),
+                    is_lambda,
+                    depth: 0,
+                    scope: None,
+                    statement,
+                });
+            }
+            Pattern::Tuple(args) => {
+                for arg in args {
+                    self.declare_pattern(arg, decl_span, kind, false, statement);
+                }
+            }
+        }
+    }
+
+    fn declare_parameter(&mut self, arg: &Anotated<Ast>) {
+        match &arg.0 {
+            Ast::Declaration(pattern, _, ty, _, value) => {
+                if let Some(ty) = ty {
+                    self.visit(ty);
+                }
+                if let Some(value) = value {
+                    self.visit(value);
+                }
+                self.declare_pattern(pattern, &arg.1, BindingKind::Parameter, false, false);
+            }
+            Ast::Variable((Token::Ident(name), span)) => {
+                self.declare(Binding {
+                    name: name.clone(),
+                    span: span.clone(),
+                    decl_span: arg.1.clone(),
+                    kind: BindingKind::Parameter,
+                    ty: arg.2.clone(),
+                    is_lambda: false,
+                    depth: 0,
+                    scope: None,
+                    statement: false,
+                });
+            }
+            _ => self.visit(arg),
+        }
+    }
+
+    fn visit(&mut self, node: &Anotated<Ast>) {
+        let statement = std::mem::take(&mut self.statement);
+        match &node.0 {
+            Ast::Error | Ast::Literal(_) | Ast::Coment(_) | Ast::Type(_) => (),
+            Ast::Variable((Token::Ident(name), span)) => match self.lookup(name) {
+                Some(index) => self.scopes.references.push((span.clone(), index)),
+                None => self.scopes.unresolved.push((name.clone(), span.clone())),
+            },
+            Ast::Variable(_) => (),
+            Ast::Declaration(pattern, _, ty, _, value) => {
+                if let Some(ty) = ty {
+                    self.visit(ty);
+                }
+                match value {
+                    // Lambdas can call themselves so the name is visible inside the body
+                    Some(value) if matches!(value.0, Ast::Lambda(..)) => {
+                        self.declare_pattern(
+                            pattern,
+                            &node.1,
+                            BindingKind::Variable,
+                            true,
+                            statement,
+                        );
+                        self.visit(value);
+                    }
+                    Some(value) => {
+                        self.visit(value);
+                        self.declare_pattern(
+                            pattern,
+                            &node.1,
+                            BindingKind::Variable,
+                            false,
+                            statement,
+                        );
+                    }
+                    None => self.declare_pattern(
+                        pattern,
+                        &node.1,
+                        BindingKind::Variable,
+                        false,
+                        statement,
+                    ),
+                }
+            }
+            Ast::Call(caller, args) => {
+                self.visit(caller);
+                for arg in args {
+                    self.visit(arg);
+                }
+            }
+            Ast::Binary(l, _, r) => {
+                self.visit(l);
+                self.visit(r);
+            }
+            Ast::While(_, cond, body) => {
+                self.visit(cond);
+                self.visit(body);
+            }
+            Ast::If(_, cond, if_body, _, else_body) => {
+                self.visit(cond);
+                self.visit(if_body);
+                self.visit(else_body);
+            }
+            Ast::Tuple(args) => {
+                for arg in args {
+                    self.visit(arg);
+                }
+            }
+            Ast::Block(expresions) => {
+                self.stack.push(Vec::new());
+                self.spans.push(node.1.clone());
+                for node in expresions {
+                    self.statement = true;
+                    self.visit(node);
+                }
+                self.spans.pop();
+                self.stack.pop();
+            }
+            Ast::Lambda(args, _, body) => {
+                self.stack.push(Vec::new());
+                self.spans.push(node.1.clone());
+                for arg in args {
+                    self.declare_parameter(arg);
+                }
+                self.visit(body);
+                self.spans.pop();
+                self.stack.pop();
+            }
+        }
+    }
+}
+
+pub fn resolve(ast: &[Anotated<Ast>]) -> Scopes {
+    let mut resolver = Resolver {
+        scopes: Scopes::default(),
+        stack: vec![Vec::new()],
+        spans: Vec::new(),
+        statement: false,
+    };
+    for node in ast {
+        resolver.statement = true;
+        resolver.visit(node);
+    }
+    resolver.scopes
+}
+
+#[cfg(test)]
+mod tests {
+    use lang_frontend::parse_file;
+
+    use super::*;
+
+    fn resolve_text(text: &str) -> Scopes {
+        let (_, ast, _) = parse_file(text);
+        resolve(&ast.expect("the test program parses").0)
+    }
+
+    fn names(scopes: &Scopes, indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|index| scopes.bindings[*index].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn uses_resolve_to_the_innermost_binding() {
+        let text = "x := 1\nf := () -> {\n    x := 2\n    x\n}\n";
+        let scopes = resolve_text(text);
+        let inner = scopes
+            .bindings
+            .iter()
+            .position(|binding| binding.name == "x" && binding.depth > 0)
+            .unwrap();
+        let use_span = text.rfind('x').unwrap();
+        assert_eq!(scopes.resolve_use(&(use_span..use_span + 1)), Some(inner));
+    }
+
+    #[test]
+    fn a_nested_declaration_shadows_the_outer_one() {
+        let scopes = resolve_text("x := 1\nf := () -> {\n    x := 2\n    x\n}\n");
+        assert_eq!(scopes.shadows.len(), 1);
+        let (binding, outer) = scopes.shadows[0];
+        // The lambda and its block are a scope each
+        assert_eq!(scopes.bindings[binding].depth, 2);
+        assert_eq!(scopes.bindings[outer].depth, 0);
+    }
+
+    #[test]
+    fn declaring_again_in_the_same_scope_is_not_shadowing() {
+        let scopes = resolve_text("f := () -> {\n    x := 1\n    x := x + 1\n    x\n}\n");
+        assert!(scopes.shadows.is_empty());
+    }
+
+    #[test]
+    fn parameters_are_bound_in_the_lambda() {
+        let text = "f := (a, b) -> a + b\n";
+        let scopes = resolve_text(text);
+        let parameters = scopes
+            .bindings
+            .iter()
+            .filter(|binding| binding.kind == BindingKind::Parameter)
+            .map(|binding| binding.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(parameters, ["a", "b"]);
+        assert_eq!(scopes.references.len(), 2);
+    }
+
+    #[test]
+    fn a_lambda_can_call_itself() {
+        let scopes = resolve_text("loop := (n) -> loop(n)\n");
+        let index = scopes
+            .bindings
+            .iter()
+            .position(|binding| binding.name == "loop")
+            .unwrap();
+        assert!(scopes.references.iter().any(|(_, b)| *b == index));
+    }
+
+    #[test]
+    fn a_value_does_not_see_its_own_name() {
+        let scopes = resolve_text("x := x + 1\n");
+        assert!(scopes.references.is_empty());
+        assert_eq!(scopes.unresolved, vec![("x".to_string(), 5..6)]);
+    }
+
+    #[test]
+    fn visible_bindings_exclude_the_ones_declared_later() {
+        let text = "a := 1\nf := () -> {\n    b := 2\n    b\n    c := 3\n    c\n}\n";
+        let scopes = resolve_text(text);
+        let pos = text.find("    b\n").unwrap() + 4;
+        let mut visible = names(&scopes, &scopes.visible_at(pos));
+        visible.sort();
+        assert_eq!(visible, ["a", "b", "f"]);
+    }
+
+    #[test]
+    fn only_statements_are_marked_as_statements() {
+        let scopes = resolve_text("f := (x) -> {\n    y := x\n    y\n}\n");
+        let statement = |name: &str| {
+            scopes
+                .bindings
+                .iter()
+                .find(|binding| binding.name == name)
+                .unwrap()
+                .statement
+        };
+        assert!(statement("f"));
+        assert!(statement("y"));
+        assert!(!statement("x"));
+    }
+}
@@ -0,0 +1,82 @@
+use lang_frontend::{
+    ast::{Anotated, Ast, Pattern},
+    token::Span,
+};
+
+fn get_selection_spans_pattern(pattern: &Anotated<Pattern>, pos: usize, spans: &mut Vec<Span>) {
+    if !pattern.1.contains(&pos) {
+        return;
+    }
+    spans.push(pattern.1.clone());
+    match &pattern.0 {
+        Pattern::Var((_, name_span)) => {
+            if name_span.contains(&pos) {
+                spans.push(name_span.clone());
+            }
+        }
+        Pattern::Tuple(args) => {
+            for arg in args {
+                get_selection_spans_pattern(arg, pos, spans);
+            }
+        }
+    }
+}
+
+// Pushes the spans of every node that contains pos, from the outermost to the innermost
+pub fn get_selection_spans(
+    (node, node_span, _): &Anotated<Ast>,
+    pos: usize,
+    spans: &mut Vec<Span>,
+) {
+    if !node_span.contains(&pos) {
+        return;
+    }
+    spans.push(node_span.clone());
+    match node {
+        Ast::Error | Ast::Type(_) | Ast::Coment(_) => (),
+        Ast::Literal((_, span)) | Ast::Variable((_, span)) => {
+            if span.contains(&pos) {
+                spans.push(span.clone());
+            }
+        }
+        Ast::Declaration(pattern, _, ty, _, value) => {
+            get_selection_spans_pattern(pattern, pos, spans);
+            if let Some(ty) = ty {
+                get_selection_spans(ty, pos, spans);
+            }
+            if let Some(value) = value {
+                get_selection_spans(value, pos, spans);
+            }
+        }
+        Ast::Call(caller, args) => {
+            get_selection_spans(caller, pos, spans);
+            for arg in args {
+                get_selection_spans(arg, pos, spans);
+            }
+        }
+        Ast::Binary(l, _, r) => {
+            get_selection_spans(l, pos, spans);
+            get_selection_spans(r, pos, spans);
+        }
+        Ast::While(_, cond, body) => {
+            get_selection_spans(cond, pos, spans);
+            get_selection_spans(body, pos, spans);
+        }
+        Ast::If(_, cond, if_body, _, else_body) => {
+            get_selection_spans(cond, pos, spans);
+            get_selection_spans(if_body, pos, spans);
+            get_selection_spans(else_body, pos, spans);
+        }
+        Ast::Tuple(args) | Ast::Block(args) => {
+            for arg in args {
+                get_selection_spans(arg, pos, spans);
+            }
+        }
+        Ast::Lambda(args, _, body) => {
+            for arg in args {
+                get_selection_spans(arg, pos, spans);
+            }
+            get_selection_spans(body, pos, spans);
+        }
+    }
+}
@@ -27,9 +27,9 @@ pub fn make_tokens_semantic(
         .iter()
         .map(|(token_type, span)| {
             // Calculamos los deltas del token
-            let line = rope.try_byte_to_line(span.start as usize).unwrap() as u32;
+            let line = rope.try_byte_to_line(span.start).unwrap() as u32;
             let first = rope.try_line_to_char(line as usize).unwrap() as u32;
-            let start = rope.try_byte_to_char(span.start as usize).unwrap() as u32 - first;
+            let start = rope.try_byte_to_char(span.start).unwrap() as u32 - first;
             let delta_line = line - pre_line;
             let delta_start = if delta_line == 0 {
                 start - pre_start
@@ -0,0 +1,56 @@
+use lang_frontend::{token::Token, types::Type};
+
+// Whether a value of type candidate can go where expected is needed. Type variables fit
+// anything, they are what inference has not decided yet
+pub fn fits(candidate: &Type, expected: &Type) -> bool {
+    match (candidate, expected) {
+        (Type::T(_), _) | (_, Type::T(_)) => true,
+        (Type::Fn(args, ret), Type::Fn(expected_args, expected_ret)) => {
+            args.len() == expected_args.len()
+                && args
+                    .iter()
+                    .zip(expected_args.iter())
+                    .all(|(arg, expected)| fits(arg, expected))
+                && fits(ret, expected_ret)
+        }
+        (Type::Tuple(members), Type::Tuple(expected_members)) => {
+            members.len() == expected_members.len()
+                && members
+                    .iter()
+                    .zip(expected_members.iter())
+                    .all(|(member, expected)| fits(member, expected))
+        }
+        _ => candidate == expected,
+    }
+}
+
+// The type the frontend gives to a literal token, None if it is not one
+pub fn literal_type(token: &Token) -> Option<Type> {
+    match token {
+        Token::Number(n) if n.contains('.') => Some(Type::Float),
+        Token::Number(_) => Some(Type::Int),
+        Token::Bool(_) => Some(Type::Bool),
+        Token::Text(_) => Some(Type::String),
+        _ => None,
+    }
+}
+
+// The same type with its variables counted from offset, to put a type table after another one
+pub fn shift_variables(ty: &Type, offset: usize) -> Type {
+    match ty {
+        Type::T(index) => Type::T(index + offset),
+        Type::Fn(args, ret) => Type::Fn(
+            args.iter()
+                .map(|arg| shift_variables(arg, offset))
+                .collect(),
+            Box::new(shift_variables(ret, offset)),
+        ),
+        Type::Tuple(members) => Type::Tuple(
+            members
+                .iter()
+                .map(|member| shift_variables(member, offset))
+                .collect(),
+        ),
+        _ => ty.clone(),
+    }
+}
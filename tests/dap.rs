@@ -0,0 +1,217 @@
+// A whole debug session against `lang-lsp dap`, over its stdio like the editor does
+use serde_json::{json, Value as Json};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// Longest wait for any message, an adapter that does not answer fails the test instead of hanging
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// The line of `total = total + double(i)` in the fixture
+const BREAKPOINT: u64 = 6;
+
+struct Client {
+    adapter: Child,
+    input: ChildStdin,
+    messages: Receiver<Json>,
+    seq: u64,
+    // Events read while waiting for a response, in order
+    events: Vec<Json>,
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Json> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+impl Client {
+    // Started away from the fixture, so only the cwd of the launch finds its imports
+    fn start() -> Client {
+        let mut adapter = Command::new(env!("CARGO_BIN_EXE_lang-lsp"))
+            .arg("dap")
+            .current_dir(std::env::temp_dir())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("the adapter starts");
+        let input = adapter.stdin.take().unwrap();
+        let mut output = BufReader::new(adapter.stdout.take().unwrap());
+        let (sender, messages) = channel();
+        thread::spawn(move || {
+            while let Some(message) = read_message(&mut output) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Client {
+            adapter,
+            input,
+            messages,
+            seq: 0,
+            events: Vec::new(),
+        }
+    }
+
+    fn read(&mut self) -> Json {
+        self.messages
+            .recv_timeout(TIMEOUT)
+            .expect("the adapter answers")
+    }
+
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        self.seq += 1;
+        let text = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{}", text.len(), text).unwrap();
+        self.input.flush().unwrap();
+        loop {
+            let message = self.read();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    // The body of a response that must succeed
+    fn ok(&mut self, command: &str, arguments: Json) -> Json {
+        let response = self.request(command, arguments);
+        assert_eq!(
+            response["success"], true,
+            "{} failed: {}",
+            command, response
+        );
+        response["body"].clone()
+    }
+
+    fn event(&mut self, name: &str) -> Json {
+        loop {
+            let message = match self.events.is_empty() {
+                true => self.read(),
+                false => self.events.remove(0),
+            };
+            if message["type"] == "event" && message["event"] == name {
+                return message["body"].clone();
+            }
+        }
+    }
+}
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/debug")
+        .canonicalize()
+        .unwrap()
+}
+
+// name -> value of what the top frame sees, the innermost binding of each name
+fn visible(client: &mut Client, frame: &Json) -> Vec<(String, String)> {
+    let scopes = client.ok("scopes", json!({ "frameId": frame["id"] }));
+    let scopes = scopes["scopes"].as_array().unwrap().clone();
+    assert_eq!(scopes.last().unwrap()["name"], "Globals");
+    let mut visible: Vec<(String, String)> = Vec::new();
+    for scope in scopes {
+        let reference = scope["variablesReference"].clone();
+        let variables = client.ok("variables", json!({ "variablesReference": reference }));
+        for variable in variables["variables"].as_array().unwrap() {
+            let name = variable["name"].as_str().unwrap().to_string();
+            if !visible.iter().any(|(seen, _)| *seen == name) {
+                visible.push((name, variable["value"].as_str().unwrap().to_string()));
+            }
+        }
+    }
+    visible
+}
+
+fn top_frame(client: &mut Client) -> Json {
+    let trace = client.ok("stackTrace", json!({ "threadId": 1 }));
+    trace["stackFrames"][0].clone()
+}
+
+#[test]
+fn stops_at_breakpoints_and_evaluates_watches() {
+    let root = fixture();
+    let program = root.join("src/main.lang");
+    let mut client = Client::start();
+
+    client.ok(
+        "initialize",
+        json!({ "adapterID": "lang", "linesStartAt1": true }),
+    );
+    client.ok("launch", json!({ "program": program, "cwd": root }));
+    client.event("initialized");
+    let body = client.ok(
+        "setBreakpoints",
+        json!({ "source": { "path": program }, "breakpoints": [{ "line": BREAKPOINT }] }),
+    );
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    assert_eq!(body["breakpoints"][0]["line"], BREAKPOINT);
+    client.ok("configurationDone", json!({}));
+
+    let stopped = client.event("stopped");
+    assert_eq!(stopped["reason"], "breakpoint");
+    let frame = top_frame(&mut client);
+    assert_eq!(frame["line"], BREAKPOINT);
+    assert_eq!(frame["source"]["path"], json!(program));
+    let variables = visible(&mut client, &frame);
+    assert!(variables.contains(&("i".to_string(), "0".to_string())));
+
+    // The loop comes back to the breakpoint once per iteration
+    client.ok("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    let frame = top_frame(&mut client);
+    let variables = visible(&mut client, &frame);
+    assert!(variables.contains(&("i".to_string(), "1".to_string())));
+
+    // double comes from the import, found from the cwd of the launch
+    let watch = client.ok(
+        "evaluate",
+        json!({ "expression": "total + double(i)", "frameId": frame["id"] }),
+    );
+    assert_eq!(watch["result"], "2");
+    for expression in ["i = 0", "y := 1"] {
+        let response = client.request(
+            "evaluate",
+            json!({ "expression": expression, "frameId": frame["id"] }),
+        );
+        assert_eq!(response["success"], false, "{} was evaluated", expression);
+    }
+    // The rejected watches did not touch the frame
+    assert_eq!(visible(&mut client, &frame), variables);
+
+    client.ok(
+        "setBreakpoints",
+        json!({ "source": { "path": program }, "breakpoints": [] }),
+    );
+    client.ok("continue", json!({ "threadId": 1 }));
+    let output = client.event("output");
+    assert_eq!(output["output"], "6\n");
+    assert_eq!(client.event("exited")["exitCode"], 0);
+    client.event("terminated");
+    client.ok("disconnect", json!({}));
+    assert!(client.adapter.wait().unwrap().success());
+}